@@ -0,0 +1,14 @@
+use clap::ValueEnum;
+
+// parses a value of an enum the same way as the command line does
+pub fn parse<T: ValueEnum>(s: &str) -> Result<T, csv::Error> {
+    T::from_str(s.trim(), true).map_err(invalid)
+}
+
+// an error for a record that was read but doesn't make sense
+pub fn invalid(message: String) -> csv::Error {
+    csv::Error::from(std::io::Error::new(
+        std::io::ErrorKind::InvalidData,
+        message,
+    ))
+}
@@ -0,0 +1,135 @@
+use crate::genes::{Genes, Trait};
+use crate::config::{invalid, parse};
+use clap::ValueEnum;
+use csv::Reader;
+use std::{fmt::Display, path::Path};
+
+/// A one-off intervention in a running simulation
+#[derive(Clone, Copy, ValueEnum)]
+pub enum EventKind {
+    /// Removes a fraction of the resources of every household
+    Wipe,
+    /// Kills a fraction of the households
+    Cull,
+    /// Resets the load of every household and forgives its debts
+    ResetLoad,
+    /// Replaces the genes of a fraction of the households
+    Mutant,
+}
+
+#[derive(Clone)]
+pub struct Event {
+    pub iteration: u32,
+    pub kind: EventKind,
+    // the id of the affected settlement, or every settlement if there is none
+    pub settlement: Option<u32>,
+    pub fraction: f64,
+    // the traits given to mutants, which keep the rest of their genes
+    pub traits: Vec<(Trait, f64)>,
+}
+
+impl Event {
+    /// Reads a CSV file with a header row and the columns iteration, event, settlement
+    ///   and fraction, followed by a column for each trait given to mutants
+    ///   (e.g. peer_transfer and subordinate_transfer), where the settlement may be
+    ///   left empty to affect every settlement and the traits are only needed by mutants
+    pub fn from_csv(path: impl AsRef<Path>) -> Result<Vec<Self>, csv::Error> {
+        let mut reader = Reader::from_path(path)?;
+
+        // the trait in each column after the first four
+        let traits = reader
+            .headers()?
+            .iter()
+            .skip(4)
+            .map(|name| parse::<Trait>(&name.replace('_', "-")))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut events = Vec::new();
+
+        for record in reader.deserialize() {
+            let (iteration, kind, settlement, fraction, values): (
+                u32,
+                String,
+                Option<u32>,
+                Option<f64>,
+                Vec<Option<f64>>,
+            ) = record?;
+
+            let kind = parse(&kind)?;
+            let traits: Vec<_> = traits
+                .iter()
+                .zip(values)
+                .filter_map(|(&t, value)| value.map(|v| (t, v)))
+                .collect();
+
+            if let (EventKind::Mutant, true) = (kind, traits.is_empty()) {
+                return Err(invalid(format!("mutants at iteration {iteration} need genes")));
+            }
+
+            events.push(Event {
+                iteration,
+                kind,
+                settlement,
+                fraction: fraction.unwrap_or(1.0).clamp(0.0, 1.0),
+                traits,
+            });
+        }
+
+        Ok(events)
+    }
+
+    // the genes of a household after it becomes a mutant
+    pub fn mutate(&self, genes: &mut Genes) {
+        for &(t, value) in &self.traits {
+            genes.set(t, value);
+        }
+    }
+}
+
+impl Display for Event {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} {} {} {}",
+            self.iteration,
+            self.kind.to_possible_value().unwrap().get_name(),
+            match self.settlement {
+                Some(id) => id.to_string(),
+                None => String::from("all"),
+            },
+            self.fraction
+        )?;
+
+        for (t, value) in &self.traits {
+            write!(f, " {}={}", t.to_possible_value().unwrap().get_name(), value)?;
+        }
+
+        Ok(())
+    }
+}
+
+// what an event did when it happened
+pub struct Record {
+    pub iteration: u32,
+    pub kind: EventKind,
+    pub settlement: Option<u32>,
+    // the number of households affected
+    pub households: usize,
+    // the resources removed from households
+    pub resources: f64,
+}
+
+impl Record {
+    pub fn fields(&self) -> Vec<String> {
+        vec![
+            self.iteration.to_string(),
+            self.kind.to_possible_value().unwrap().get_name().to_string(),
+            match self.settlement {
+                Some(id) => id.to_string(),
+                None => String::from("all"),
+            },
+            self.households.to_string(),
+            self.resources.to_string(),
+        ]
+    }
+}
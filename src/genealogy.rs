@@ -0,0 +1,113 @@
+use std::collections::HashMap;
+
+// a record of a household being born
+pub struct Birth {
+    pub iteration: u32,
+    pub child: u32,
+    pub parent: u32,
+    pub mate: Option<u32>,
+    // the id of the settlement the child lives in
+    pub settlement: u32,
+}
+
+impl Birth {
+    pub fn fields(&self) -> Vec<String> {
+        vec![
+            self.iteration.to_string(),
+            self.child.to_string(),
+            self.parent.to_string(),
+            self.mate.map(|m| m.to_string()).unwrap_or_default(),
+            self.settlement.to_string(),
+        ]
+    }
+}
+
+/// Writes the descendants of each founder through their parents as a tree in the Newick format,
+///   one per line, where the branch lengths are the iterations between births
+pub fn newick(births: &[Birth], founders: u32) -> String {
+    let mut children: HashMap<u32, Vec<&Birth>> = HashMap::new();
+    for birth in births {
+        children.entry(birth.parent).or_default().push(birth);
+    }
+
+    let mut trees = String::new();
+    for founder in 0..founders {
+        trees.push_str(&tree(founder, &children));
+        trees.push_str(";\n");
+    }
+
+    trees
+}
+
+// lineages can be thousands of generations deep, so the tree is written
+//   with a stack rather than recursively
+fn tree(founder: u32, children: &HashMap<u32, Vec<&Birth>>) -> String {
+    let mut tree = String::new();
+
+    // each household being written, the iteration it was born in,
+    //   the index of its next child to write and the length of its branch
+    let mut stack = vec![(founder, 0, 0, None)];
+
+    while let Some((id, born, next, length)) = stack.pop() {
+        let births = children.get(&id).map(|b| &b[..]).unwrap_or(&[]);
+
+        if next < births.len() {
+            tree.push(if next == 0 { '(' } else { ',' });
+
+            let child = births[next];
+            stack.push((id, born, next + 1, length));
+            stack.push((child.child, child.iteration, 0, Some(child.iteration - born)));
+            continue;
+        }
+
+        if !births.is_empty() {
+            tree.push(')');
+        }
+
+        tree.push_str(&id.to_string());
+        if let Some(length) = length {
+            tree.push_str(&format!(":{length}"));
+        }
+    }
+
+    tree
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn birth(iteration: u32, child: u32, parent: u32) -> Birth {
+        Birth {
+            iteration,
+            child,
+            parent,
+            mate: None,
+            settlement: 0,
+        }
+    }
+
+    #[test]
+    fn founders_without_children_are_leaves() {
+        assert_eq!(newick(&[], 2), "0;\n1;\n");
+    }
+
+    #[test]
+    fn branches_are_the_iterations_between_births() {
+        let births = [birth(3, 5, 0), birth(7, 6, 0), birth(10, 8, 5), birth(4, 9, 1)];
+
+        assert_eq!(newick(&births, 2), "((8:7)5:3,6:7)0;\n(9:4)1;\n");
+    }
+
+    #[test]
+    fn deep_lineages_are_written() {
+        let depth = 100_000;
+        let births: Vec<_> = (1..=depth).map(|i| birth(i, i, i - 1)).collect();
+
+        let trees = newick(&births, 1);
+
+        assert!(trees.starts_with(&"(".repeat(depth as usize)));
+        assert!(trees.ends_with(")0;\n"));
+        assert_eq!(trees.matches(')').count(), depth as usize);
+    }
+}
@@ -0,0 +1,525 @@
+use crate::config::{invalid, parse};
+use clap::ValueEnum;
+use csv::Reader;
+use rand::Rng;
+use rand_distr::{Beta, Distribution, StandardNormal};
+use std::{fmt::Display, path::Path, str::FromStr};
+
+const MUTATION_FREQ: f64 = 0.33;
+const MUTATION_AMPL: f64 = 0.25;
+
+// the number of traits in every genome
+pub const TRAITS: usize = Trait::ALL.len();
+
+/// A heritable behavioural trait, from 0 to 1
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Trait {
+    /// The likelihood of donating to peers
+    PeerTransfer,
+    /// The likelihood of donating to subordinates
+    SubordinateTransfer,
+    /// How attached a household is to its settlement
+    Attachment,
+    /// The shortfall, as a fraction of consumption, that a household endures before asking for help
+    RequestThreshold,
+    /// The largest fraction of its resources a household gives away in one donation
+    DonationFraction,
+    /// How much a household favours those that helped it before and shuns those that refused it
+    Reciprocity,
+    /// How much a household favours those with a good image and shuns those with a bad one
+    Discrimination,
+}
+
+impl Trait {
+    // new traits only need to be added here and to the methods below
+    pub const ALL: [Trait; 7] = [
+        Trait::PeerTransfer,
+        Trait::SubordinateTransfer,
+        Trait::Attachment,
+        Trait::RequestThreshold,
+        Trait::DonationFraction,
+        Trait::Reciprocity,
+        Trait::Discrimination,
+    ];
+
+    // the name of the trait in the results
+    pub fn name(&self) -> &'static str {
+        match self {
+            Trait::PeerTransfer => "PeerTransfer",
+            Trait::SubordinateTransfer => "SubTransfer",
+            Trait::Attachment => "Attachment",
+            Trait::RequestThreshold => "RequestThreshold",
+            Trait::DonationFraction => "DonationFraction",
+            Trait::Reciprocity => "Reciprocity",
+            Trait::Discrimination => "Discrimination",
+        }
+    }
+
+    fn altruistic(&self) -> f64 {
+        match self {
+            Trait::PeerTransfer | Trait::SubordinateTransfer => 1.0,
+            Trait::Attachment => 0.5,
+            Trait::RequestThreshold => 0.0,
+            Trait::DonationFraction => 1.0,
+            Trait::Reciprocity | Trait::Discrimination => 0.0,
+        }
+    }
+
+    fn defective(&self) -> f64 {
+        match self {
+            Trait::PeerTransfer | Trait::SubordinateTransfer => 0.0,
+            _ => self.altruistic(),
+        }
+    }
+
+    // only the transfer traits mutate by default, so the other traits
+    //   don't change the behaviour of the model unless they are enabled
+    fn mutation(&self) -> Mutation {
+        match self {
+            Trait::PeerTransfer | Trait::SubordinateTransfer => {
+                Mutation::new(MUTATION_FREQ, MUTATION_AMPL)
+            }
+            _ => Mutation::new(0.0, MUTATION_AMPL),
+        }
+    }
+}
+
+/// Where a child's genes come from
+#[derive(Clone, Copy, ValueEnum)]
+pub enum GeneInheritance {
+    /// Each trait comes from the parent or its partner at random
+    RandomParent,
+    /// Each trait is the average of the parent's and its partner's
+    Blending,
+    /// Every trait comes from the parent
+    Clonal,
+}
+
+/// The distribution of the change in a trait when it mutates
+#[derive(Clone, Copy, ValueEnum)]
+pub enum MutationKernel {
+    /// Uniform within the amplitude either side of the trait
+    Uniform,
+    /// Normal with the amplitude as its standard deviation
+    Gaussian,
+}
+
+/// What happens to a trait that mutates beyond 0 or 1
+#[derive(Clone, Copy, ValueEnum)]
+pub enum MutationBounds {
+    /// It is held at the bound
+    Clamp,
+    /// It is reflected back from the bound
+    Reflect,
+    /// It continues from the opposite bound
+    Wrap,
+}
+
+impl MutationBounds {
+    fn apply(&self, value: f64) -> f64 {
+        if (0.0..=1.0).contains(&value) {
+            return value;
+        }
+
+        match self {
+            MutationBounds::Clamp => value.clamp(0.0, 1.0),
+            // reflecting from both bounds repeats every 2
+            MutationBounds::Reflect => {
+                let value = value.rem_euclid(2.0);
+                if value > 1.0 {
+                    2.0 - value
+                } else {
+                    value
+                }
+            }
+            MutationBounds::Wrap => value.rem_euclid(1.0),
+        }
+    }
+}
+
+// how genes are passed from parents to their children
+#[derive(Clone, Copy)]
+pub struct Heredity {
+    pub inheritance: GeneInheritance,
+    pub kernel: MutationKernel,
+    pub bounds: MutationBounds,
+    // how each trait mutates, in the order of Trait::ALL
+    pub mutation: [Mutation; TRAITS],
+}
+
+impl Default for Heredity {
+    fn default() -> Self {
+        Heredity {
+            inheritance: GeneInheritance::RandomParent,
+            kernel: MutationKernel::Uniform,
+            bounds: MutationBounds::Clamp,
+            mutation: Mutation::defaults(),
+        }
+    }
+}
+
+/// How a trait mutates when it is inherited
+#[derive(Clone, Copy)]
+pub struct Mutation {
+    pub frequency: f64,
+    pub amplitude: f64,
+}
+
+impl Mutation {
+    fn new(frequency: f64, amplitude: f64) -> Self {
+        Mutation {
+            frequency,
+            amplitude,
+        }
+    }
+
+    // the default mutation of every trait
+    pub fn defaults() -> [Self; TRAITS] {
+        Trait::ALL.map(|t| t.mutation())
+    }
+}
+
+// a mutation of one trait, written as "trait=frequency,amplitude"
+#[derive(Clone, Copy)]
+pub struct TraitMutation(pub Trait, pub Mutation);
+
+impl FromStr for TraitMutation {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, parameters) = s
+            .split_once('=')
+            .ok_or_else(|| String::from("expected TRAIT=FREQUENCY,AMPLITUDE"))?;
+
+        let t = Trait::from_str(&name.trim().replace('_', "-"), true)?;
+
+        let (frequency, amplitude) = parameters
+            .split_once(',')
+            .ok_or_else(|| String::from("expected TRAIT=FREQUENCY,AMPLITUDE"))?;
+
+        let frequency = frequency.trim().parse().map_err(|e| format!("{e}"))?;
+        let amplitude = amplitude.trim().parse().map_err(|e| format!("{e}"))?;
+
+        Ok(TraitMutation(t, Mutation::new(frequency, amplitude)))
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub struct Genes {
+    // the value of each trait, in the order of Trait::ALL
+    values: [f64; TRAITS],
+}
+
+impl Genes {
+    fn new(values: [f64; TRAITS]) -> Self {
+        Genes { values }
+    }
+
+    pub fn altruistic() -> Self {
+        Genes::new(Trait::ALL.map(|t| t.altruistic()))
+    }
+
+    pub fn defective() -> Self {
+        Genes::new(Trait::ALL.map(|t| t.defective()))
+    }
+
+    pub fn get(&self, t: Trait) -> f64 {
+        self.values[t as usize]
+    }
+
+    pub fn set(&mut self, t: Trait, value: f64) {
+        self.values[t as usize] = value.clamp(0.0, 1.0);
+    }
+
+    // the genes of the child of a household with these genes and a partner with other
+    pub fn combine(&self, other: Self, heredity: &Heredity) -> Self {
+        Genes::new(std::array::from_fn(|i| {
+            let new_gene = match heredity.inheritance {
+                GeneInheritance::RandomParent => {
+                    if rand::random() {
+                        self.values[i]
+                    } else {
+                        other.values[i]
+                    }
+                }
+                GeneInheritance::Blending => (self.values[i] + other.values[i]) / 2.0,
+                GeneInheritance::Clonal => self.values[i],
+            };
+
+            Self::mutate(new_gene, heredity.mutation[i], heredity)
+        }))
+    }
+
+    fn mutate(mut new_gene: f64, mutation: Mutation, heredity: &Heredity) -> f64 {
+        if rand::random::<f64>() < mutation.frequency {
+            new_gene += mutation.amplitude
+                * match heredity.kernel {
+                    MutationKernel::Uniform => rand::random::<f64>() * 2.0 - 1.0,
+                    MutationKernel::Gaussian => rand::thread_rng().sample::<f64, _>(StandardNormal),
+                };
+        }
+
+        heredity.bounds.apply(new_gene)
+    }
+
+    // how different two sets of genes are
+    pub fn distance(&self, other: &Self) -> f64 {
+        self.values
+            .iter()
+            .zip(other.values.iter())
+            .map(|(a, b)| (a - b).powi(2))
+            .sum::<f64>()
+            .sqrt()
+    }
+
+    pub fn cooperation(&self) -> f64 {
+        (self.get(Trait::PeerTransfer) + self.get(Trait::SubordinateTransfer)) / 2.0
+    }
+}
+
+// the distribution an initial trait is drawn from
+#[derive(Clone, Copy)]
+pub enum TraitDistribution {
+    Fixed(f64),
+    Uniform(f64, f64),
+    Beta(f64, f64),
+}
+
+impl TraitDistribution {
+    fn sample(&self, rng: &mut impl Rng) -> f64 {
+        match *self {
+            TraitDistribution::Fixed(value) => value,
+            TraitDistribution::Uniform(low, high) => rng.gen_range(low..=high),
+            // the parameters are checked when the distribution is read
+            TraitDistribution::Beta(a, b) => Beta::new(a, b).unwrap().sample(rng),
+        }
+    }
+}
+
+impl FromStr for TraitDistribution {
+    type Err = String;
+
+    // distributions are written as a value, "uniform:LOW,HIGH" or "beta:A,B"
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, parameters) = s.trim().split_once(':').unwrap_or((s.trim(), ""));
+
+        let p = parameters
+            .split(',')
+            .filter(|p| !p.trim().is_empty())
+            .map(|p| p.trim().parse::<f64>().map_err(|e| e.to_string()))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        match (name, &p[..]) {
+            ("uniform", &[low, high]) if 0.0 <= low && low <= high && high <= 1.0 => {
+                Ok(TraitDistribution::Uniform(low, high))
+            }
+            ("beta", &[a, b]) if a > 0.0 && b > 0.0 && a.is_finite() && b.is_finite() => {
+                Ok(TraitDistribution::Beta(a, b))
+            }
+            (value, []) => match value.parse::<f64>() {
+                Ok(value) if (0.0..=1.0).contains(&value) => Ok(TraitDistribution::Fixed(value)),
+                _ => Err(format!(
+                    "expected a value from 0 to 1, uniform:LOW,HIGH or beta:A,B, not {s}"
+                )),
+            },
+            _ => Err(format!(
+                "expected a value from 0 to 1, uniform:LOW,HIGH or beta:A,B, not {s}"
+            )),
+        }
+    }
+}
+
+impl Display for TraitDistribution {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TraitDistribution::Fixed(value) => write!(f, "{value}"),
+            TraitDistribution::Uniform(low, high) => write!(f, "uniform:{low},{high}"),
+            TraitDistribution::Beta(a, b) => write!(f, "beta:{a},{b}"),
+        }
+    }
+}
+
+/// The genes a strategy starts from before any of its traits are drawn
+#[derive(Clone, Copy, ValueEnum)]
+pub enum Strategy {
+    Altruistic,
+    Defective,
+}
+
+// a share of the initial households and the distributions of their traits
+#[derive(Clone)]
+pub struct Group {
+    // the id of the settlement the group belongs to, or every settlement if there is none
+    settlement: Option<u32>,
+    fraction: f64,
+    strategy: Strategy,
+    traits: Vec<(Trait, TraitDistribution)>,
+}
+
+impl Group {
+    fn new(fraction: f64, strategy: Strategy) -> Self {
+        Group {
+            settlement: None,
+            fraction,
+            strategy,
+            traits: Vec::new(),
+        }
+    }
+
+    fn genes(&self, rng: &mut impl Rng) -> Genes {
+        let mut genes = match self.strategy {
+            Strategy::Altruistic => Genes::altruistic(),
+            Strategy::Defective => Genes::defective(),
+        };
+
+        for (t, distribution) in &self.traits {
+            genes.set(*t, distribution.sample(rng));
+        }
+
+        genes
+    }
+}
+
+impl Display for Group {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} {} {}",
+            match self.settlement {
+                Some(id) => id.to_string(),
+                None => String::from("all"),
+            },
+            self.fraction,
+            self.strategy.to_possible_value().unwrap().get_name(),
+        )?;
+
+        for (t, distribution) in &self.traits {
+            write!(f, " {}={}", t.to_possible_value().unwrap().get_name(), distribution)?;
+        }
+
+        Ok(())
+    }
+}
+
+// how the genes of the initial households are chosen
+#[derive(Clone)]
+pub struct InitialGenes {
+    groups: Vec<Group>,
+}
+
+impl InitialGenes {
+    // every household starts with the genes of the strategy
+    pub fn uniform(strategy: Strategy) -> Self {
+        InitialGenes {
+            groups: vec![Group::new(1.0, strategy)],
+        }
+    }
+
+    // the households alternate between the two strategies
+    pub fn split() -> Self {
+        InitialGenes {
+            groups: vec![
+                Group::new(0.5, Strategy::Altruistic),
+                Group::new(0.5, Strategy::Defective),
+            ],
+        }
+    }
+
+    /// Reads a CSV file with a header row and the columns settlement, fraction and strategy,
+    ///   followed by a column for each trait drawn from a distribution (e.g. peer_transfer),
+    ///   where the settlement may be left empty for every settlement without its own rows
+    pub fn from_csv(path: impl AsRef<Path>) -> Result<Self, csv::Error> {
+        let mut reader = Reader::from_path(path)?;
+
+        // the trait in each column after the first three
+        let traits = reader
+            .headers()?
+            .iter()
+            .skip(3)
+            .map(|name| parse::<Trait>(&name.replace('_', "-")))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut groups = Vec::new();
+
+        for record in reader.deserialize() {
+            let (settlement, fraction, strategy, distributions): (
+                Option<u32>,
+                f64,
+                String,
+                Vec<Option<String>>,
+            ) = record?;
+
+            if !(fraction > 0.0 && fraction.is_finite()) {
+                return Err(invalid(format!("the fraction {fraction} must be finite and positive")));
+            }
+
+            let mut group = Group::new(fraction, parse(&strategy)?);
+            group.settlement = settlement;
+
+            for (&t, distribution) in traits.iter().zip(distributions) {
+                if let Some(distribution) = distribution {
+                    group.traits.push((t, distribution.parse().map_err(invalid)?));
+                }
+            }
+
+            groups.push(group);
+        }
+
+        let initial_genes = InitialGenes { groups };
+
+        if let Some(id) =
+            (0..crate::SETTLEMENTS as u32).find(|&id| initial_genes.groups(id).is_empty())
+        {
+            return Err(invalid(format!("settlement {id} has no initial genes")));
+        }
+
+        Ok(initial_genes)
+    }
+
+    // the groups of a settlement, which are those for every settlement unless it has its own
+    fn groups(&self, id: u32) -> Vec<&Group> {
+        let own: Vec<_> = self
+            .groups
+            .iter()
+            .filter(|g| g.settlement == Some(id))
+            .collect();
+
+        if own.is_empty() {
+            self.groups.iter().filter(|g| g.settlement.is_none()).collect()
+        } else {
+            own
+        }
+    }
+
+    // the genes of the initial households of a settlement, where the groups are interleaved
+    //   so that every prefix of the households is as close to the fractions as possible
+    pub fn generate(&self, id: u32, households: usize, rng: &mut impl Rng) -> Vec<Genes> {
+        let groups = self.groups(id);
+        let total: f64 = groups.iter().map(|g| g.fraction).sum();
+        let mut assigned = vec![0; groups.len()];
+
+        (0..households)
+            .map(|i| {
+                // the group that is furthest behind its share, the first if they are tied
+                let mut chosen = 0;
+                let mut deficit = f64::NEG_INFINITY;
+                for (k, group) in groups.iter().enumerate() {
+                    let d = group.fraction / total * (i + 1) as f64 - assigned[k] as f64;
+                    if d > deficit {
+                        (chosen, deficit) = (k, d);
+                    }
+                }
+
+                assigned[chosen] += 1;
+                groups[chosen].genes(rng)
+            })
+            .collect()
+    }
+}
+
+impl Display for InitialGenes {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let groups: Vec<_> = self.groups.iter().map(|g| g.to_string()).collect();
+        write!(f, "{}", groups.join("; "))
+    }
+}
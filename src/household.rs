@@ -1,10 +1,11 @@
+use crate::genes::{Genes, Heredity, Trait};
 use crate::world::Index;
+use clap::ValueEnum;
+use std::collections::VecDeque;
 
 // TODO: move other constants here
 const CONSUMPTION: f64 = 0.5;
 const L: f64 = 0.6;
-const MUTATION_FREQ: f64 = 0.33;
-const MUTATION_AMPL: f64 = 0.25;
 
 pub struct Household {
     pub id: u32,
@@ -15,6 +16,21 @@ pub struct Household {
     pub genes: Genes,
     pub years_since_move: u32,
     pub satisfaction: f64,
+    // the number of iterations the household has existed for
+    pub age: u32,
+    // the ids of the household this one was born to and its partner,
+    //   which are unique across the whole simulation
+    pub parent: Option<u32>,
+    pub mate: Option<u32>,
+    // the id of the initial household this one descends from through its parents
+    pub founder: u32,
+    // the households this one owes for their donations and how much, oldest first
+    pub debts: Vec<(u32, f64)>,
+    // the households this one asked for help and whether they helped, oldest first
+    pub memory: VecDeque<(u32, bool)>,
+    // the standing of the household in its settlement, from -1 if it refuses
+    //   every request it can afford to 1 if it helps with every one
+    pub image: f64,
 }
 
 impl Household {
@@ -29,6 +45,13 @@ impl Household {
             genes,
             years_since_move: 0,
             satisfaction: 0.0,
+            age: 0,
+            parent: None,
+            mate: None,
+            founder: id,
+            debts: Vec::new(),
+            memory: VecDeque::new(),
+            image: 0.0,
         }
     }
 
@@ -36,6 +59,12 @@ impl Household {
         f64::max(CONSUMPTION - self.resources, 0.0)
     }
 
+    // whether the household is short enough of resources to ask others for help
+    pub fn requesting(&self) -> bool {
+        self.required() > 0.0
+            && self.required() >= self.genes.get(Trait::RequestThreshold) * CONSUMPTION
+    }
+
     pub fn provide(&mut self, resources: f64) {
         self.resources += resources;
     }
@@ -48,27 +77,120 @@ impl Household {
         // TODO: update satisfaction
     }
 
-    pub fn query_donation(&mut self, required: f64, query_type: QueryType, chance: f64) -> bool {
-        // don't have the resources to donate
-        if required > self.resources {
-            return false;
+    // how much of the request the household can afford to give, which under all-or-nothing
+    //   sharing is either all of it or nothing, and otherwise is as much as it is willing to part with
+    pub fn affordable(&self, requested: f64, sharing: Sharing) -> f64 {
+        // the most the household will give away in one donation
+        let available = self.resources * self.genes.get(Trait::DonationFraction);
+
+        match sharing {
+            Sharing::AllOrNothing if requested > available => 0.0,
+            Sharing::AllOrNothing => requested,
+            Sharing::Pooled => f64::min(requested, available),
         }
+    }
 
-        let donating = match query_type {
-            QueryType::Superior => true,
-            QueryType::Peer => chance < self.genes.peer_transfer,
-            QueryType::Subordinate => chance < self.genes.subordinate_transfer,
+    // whether the household agrees to donate to the requester
+    pub fn willing(&self, requester: &Household, query_type: QueryType, chance: f64) -> bool {
+        let transfer = match query_type {
+            QueryType::Superior => return true,
+            QueryType::Peer => self.genes.get(Trait::PeerTransfer),
+            QueryType::Subordinate => self.genes.get(Trait::SubordinateTransfer),
         };
 
-        if donating {
-            self.resources -= required;
-            self.load += required;
+        // reciprocators lean towards always or never donating
+        //   depending on how the requester treated them before
+        let reciprocity = self.genes.get(Trait::Reciprocity);
+        let transfer = match self.regard(requester.id) {
+            Some(regard) => Self::lean(transfer, reciprocity * regard),
+            None => transfer,
+        };
+
+        // and discriminators do the same depending on the requester's image
+        let discrimination = self.genes.get(Trait::Discrimination);
+        let transfer = Self::lean(transfer, discrimination * requester.image);
+
+        chance < transfer
+    }
+
+    pub fn donate(&mut self, amount: f64) {
+        self.resources -= amount;
+        self.load += amount;
+    }
+
+    // moves a probability towards 1 by the fraction of the way if it is positive,
+    //   and towards 0 if it is negative
+    fn lean(probability: f64, fraction: f64) -> f64 {
+        if fraction >= 0.0 {
+            probability + fraction * (1.0 - probability)
+        } else {
+            probability + fraction * probability
+        }
+    }
+
+    // the image of the household rises when it helps and falls when it refuses
+    pub fn update_image(&mut self, helped: bool, step: f64) {
+        let change = if helped { step } else { -step };
+        self.image = (self.image + change).clamp(-1.0, 1.0);
+    }
+
+    // remembers whether the household with the id helped this one when asked,
+    //   forgetting the oldest interactions beyond the capacity
+    pub fn remember(&mut self, id: u32, helped: bool, capacity: usize) {
+        if capacity == 0 {
+            return;
         }
 
-        donating
+        if self.memory.len() == capacity {
+            self.memory.pop_front();
+        }
+
+        self.memory.push_back((id, helped));
+    }
+
+    // from -1 if the household with the id always refused this one to 1 if it always helped,
+    //   or none if this one doesn't remember asking it
+    pub fn regard(&self, id: u32) -> Option<f64> {
+        let (helped, asked) = self
+            .memory
+            .iter()
+            .filter(|&&(other, _)| other == id)
+            .fold((0, 0), |(helped, asked), &(_, h)| (helped + h as i32, asked + 1));
+
+        if asked == 0 {
+            None
+        } else {
+            Some((2 * helped - asked) as f64 / asked as f64)
+        }
+    }
+
+    pub fn owe(&mut self, creditor: u32, amount: f64) {
+        match self.debts.iter_mut().find(|(id, _)| *id == creditor) {
+            Some((_, debt)) => *debt += amount,
+            None => self.debts.push((creditor, amount)),
+        }
+    }
+
+    pub fn debt(&self) -> f64 {
+        self.debts.iter().map(|(_, debt)| debt).sum()
     }
 
-    pub fn birth_new(&mut self, genes: Genes, id: u32) -> Self {
+    // the load and what is owed for it are forgotten over time
+    pub fn decay_load(&mut self, rate: f64) {
+        self.load *= 1.0 - rate;
+
+        for (_, debt) in &mut self.debts {
+            *debt *= 1.0 - rate;
+        }
+    }
+
+    pub fn birth_new(
+        &mut self,
+        genes: Genes,
+        mate: Option<u32>,
+        id: u32,
+        heredity: &Heredity,
+    ) -> Self {
         // TODO: check if other attributes need to be changed
         // resources are split between parent and child
         self.resources /= 2.0;
@@ -76,12 +198,20 @@ impl Household {
         Household {
             id,
             resources: self.resources,
-            hunger: 0.0,
+            // the child is born after the parent has eaten, so they start equally fed
+            hunger: self.hunger,
             resource_patch: None,
             load: 0.0,
-            genes: self.genes.combine(genes),
+            genes: self.genes.combine(genes, heredity),
             years_since_move: 0,
             satisfaction: 0.0,
+            age: 0,
+            parent: Some(self.id),
+            mate,
+            founder: self.founder,
+            debts: Vec::new(),
+            memory: VecDeque::new(),
+            image: 0.0,
         }
     }
 
@@ -102,13 +232,40 @@ impl Household {
         (self.status() - other_status) / f64::max(other_status, self.status()) > L
     }
 
-    pub fn birth(&self, chance: f64) -> bool {
-        chance < self.hunger * crate::BIRTH_RATE
+    pub fn birth(&self, chance: f64, birth_rate: f64, maturity: u32) -> bool {
+        self.age >= maturity && chance < self.hunger * birth_rate
     }
 
-    pub fn death(&self, chance: f64) -> bool {
-        // chance * self.hunger < crate::DEATH_RATE
-        chance < crate::DEATH_RATE
+    pub fn death(
+        &self,
+        chance: f64,
+        death_rate: f64,
+        mortality: Mortality,
+        gompertz: (f64, f64),
+    ) -> Option<Cause> {
+        // the probability of starving is death_rate / self.hunger,
+        //   of which death_rate is already accounted for by the constant rate
+        let starvation = match mortality {
+            Mortality::Hunger | Mortality::Combined => death_rate / self.hunger - death_rate,
+            _ => 0.0,
+        };
+
+        // the Gompertz law, where the probability of dying grows exponentially with age
+        let senescence = match mortality {
+            Mortality::Age | Mortality::Combined => gompertz.0 * (gompertz.1 * self.age as f64).exp(),
+            _ => 0.0,
+        };
+
+        // the causes are checked against the same chance, so each death has one cause
+        if chance < death_rate {
+            Some(Cause::Background)
+        } else if chance < death_rate + starvation {
+            Some(Cause::Starvation)
+        } else if chance < death_rate + starvation + senescence {
+            Some(Cause::Senescence)
+        } else {
+            None
+        }
     }
 
     // TODO: obviously you need to get migration working...
@@ -121,7 +278,7 @@ impl Household {
     }
 
     pub fn movement(&self, chance: f64) -> bool {
-        2.0 * self.genes.attachment * self.satisfaction
+        2.0 * self.genes.get(Trait::Attachment) * self.satisfaction
             < chance
     }
 
@@ -133,50 +290,58 @@ impl Household {
     }*/
 }
 
-#[derive(Clone, Copy, PartialEq)]
-pub struct Genes {
-    pub peer_transfer: f64,        // likelihood of contributing to peers
-    pub subordinate_transfer: f64, // ditto for subordinates
+/// How the probability of a household dying is determined
+#[derive(Clone, Copy, ValueEnum)]
+pub enum Mortality {
+    /// Every household has the same probability of dying
+    Constant,
+    /// Households are more likely to die the hungrier they are
+    Hunger,
+    /// Households are more likely to die the older they are
+    Age,
+    /// Households are more likely to die the hungrier and older they are
+    Combined,
 }
 
-impl Genes {
-    fn new(peer_transfer: f64, subordinate_transfer: f64) -> Self {
-        Genes {
-            peer_transfer,
-            subordinate_transfer,
-        }
-    }
-
-    pub fn altruistic() -> Self {
-        Genes::new(1.0, 1.0)
-    }
-
-    pub fn defective() -> Self {
-        Genes::new(0.0, 0.0)
-    }
-
-    fn combine(&self, other: Self) -> Self {
-        Genes::new(
-            Self::random_choice(self.peer_transfer, other.peer_transfer),
-            Self::random_choice(self.subordinate_transfer, other.subordinate_transfer),
-        )
-    }
-
-    fn random_choice(first: f64, second: f64) -> f64 {
-        let mut new_gene = if rand::random() { first } else { second };
+/// What the probability of a household reproducing depends on, besides its hunger
+#[derive(Clone, Copy, ValueEnum)]
+pub enum Reproduction {
+    /// Only the hunger of the household
+    Hunger,
+    /// How crowded the household's settlement is
+    Density,
+    /// How much unclaimed land surrounds the household's settlement
+    Land,
+    /// Both how crowded and how much unclaimed land surrounds the settlement
+    Combined,
+}
 
-        if rand::random::<f64>() < MUTATION_FREQ {
-            new_gene += MUTATION_AMPL * (rand::random::<f64>() * 2.0 - 1.0)
-        }
+/// How households respond to a request for resources
+#[derive(Clone, Copy, ValueEnum)]
+pub enum Sharing {
+    /// The first donor gives everything requested, and a donor can't give less
+    AllOrNothing,
+    /// Donors give what they can, which is pooled until the request is met
+    Pooled,
+}
 
-        new_gene.clamp(0.0, 1.0)
-    }
+/// When donations take effect
+#[derive(Clone, Copy, ValueEnum)]
+pub enum Update {
+    /// As soon as they are made, so later requests see the effects of earlier donations
+    Asynchronous,
+    /// Together once every request has been made against the resources at the start
+    Synchronous,
+}
 
-    pub fn cooperation(&self) -> f64 {
-        (self.peer_transfer + self.subordinate_transfer) / 2.0
-    }
+#[derive(Clone, Copy)]
+pub enum Cause {
+    Background,
+    Starvation,
+    Senescence,
 }
 
+#[derive(Clone, Copy)]
 pub enum QueryType {
     Superior,
     Peer,
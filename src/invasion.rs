@@ -0,0 +1,117 @@
+use crate::genes::Genes;
+use crate::world::World;
+use crate::Settings;
+use clap::ValueEnum;
+
+// how mutants are introduced into a population fixed on one strategy
+pub struct Invasion {
+    pub trials: u32,
+    // the iteration the mutants are introduced at
+    pub iteration: u32,
+    // the number of iterations each trial lasts after the mutants are introduced
+    pub length: u32,
+    pub mutants: usize,
+    // the id of the settlement the mutants are introduced into, or a random one if there is none
+    pub settlement: Option<u32>,
+}
+
+/// What became of the mutants by the end of a trial
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+pub enum Outcome {
+    /// Every household has the mutant strategy
+    Fixation,
+    /// No household has the mutant strategy
+    Extinction,
+    /// Households have both the mutant and the resident strategies
+    Coexistence,
+    /// The population died out
+    Collapse,
+}
+
+pub struct Trial {
+    pub introduced: usize,
+    // the fraction of the population that were mutants when they were introduced
+    pub initial: f64,
+    pub iteration: u32,
+    pub population: usize,
+    // the fraction of the population with the mutant strategy
+    pub frequency: f64,
+    pub cooperation: f64,
+    pub outcome: Outcome,
+}
+
+impl Trial {
+    // whether the mutants grew from their initial share of the population
+    pub fn invaded(&self) -> bool {
+        self.outcome != Outcome::Collapse && self.frequency > self.initial
+    }
+
+    pub fn fields(&self) -> Vec<String> {
+        vec![
+            self.introduced.to_string(),
+            self.initial.to_string(),
+            self.iteration.to_string(),
+            self.population.to_string(),
+            self.frequency.to_string(),
+            self.cooperation.to_string(),
+            self.outcome.to_possible_value().unwrap().get_name().to_string(),
+        ]
+    }
+}
+
+impl Invasion {
+    // the settings of the experiment that are recorded alongside the results
+    pub fn metadata(&self) -> Vec<(&'static str, String)> {
+        vec![
+            ("Trials", self.trials.to_string()),
+            ("InvasionIteration", self.iteration.to_string()),
+            ("InvasionLength", self.length.to_string()),
+            ("Mutants", self.mutants.to_string()),
+            (
+                "InvasionSettlement",
+                match self.settlement {
+                    Some(id) => id.to_string(),
+                    None => String::from("random"),
+                },
+            ),
+        ]
+    }
+
+    // runs one simulation in which mutants with the genes are introduced among the residents
+    pub fn trial(&self, settings: &Settings, resident: Genes, mutant: Genes) -> Trial {
+        let mut world = World::new(settings.clone());
+
+        while world.iteration() < self.iteration && world.count_population() > 0 {
+            world.iterate();
+        }
+
+        let introduced = world.introduce(self.settlement, self.mutants, mutant);
+        let initial = introduced as f64 / world.count_population() as f64;
+
+        // either strategy can reappear through mating and mutation, so the trial runs its length
+        while world.iteration() < self.iteration + self.length && world.count_population() > 0 {
+            world.iterate();
+        }
+
+        let population = world.count_population();
+        let mutants = world.count_mutants(&resident, &mutant);
+
+        Trial {
+            introduced,
+            initial,
+            iteration: world.iteration(),
+            population,
+            frequency: mutants as f64 / population as f64,
+            cooperation: world.average_cooperation(),
+            outcome: if population == 0 {
+                Outcome::Collapse
+            } else if mutants == 0 {
+                Outcome::Extinction
+            } else if mutants == population {
+                Outcome::Fixation
+            } else {
+                Outcome::Coexistence
+            },
+        }
+    }
+}
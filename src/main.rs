@@ -1,18 +1,42 @@
 #![allow(warnings)]
 #![feature(extract_if)]
 
+mod config;
+mod events;
+mod genealogy;
+mod genes;
 mod household;
+mod invasion;
+mod mating;
+mod price;
+mod schedule;
 mod settlement;
+mod statistics;
+mod stress;
 mod visualiser;
 mod world;
 
+use crate::events::Event;
+use crate::genes::{
+    GeneInheritance, Genes, Heredity, InitialGenes, MutationBounds, MutationKernel, Strategy, Trait,
+    TraitMutation,
+};
+use crate::household::{Cause, Mortality, Reproduction, Sharing, Update};
+use crate::invasion::{Invasion, Outcome, Trial};
+use crate::mating::MateSelection;
+use crate::price::{Price, Snapshot};
+use crate::schedule::Schedule;
+use crate::settlement::{LandInheritance, Residence, ResourceInheritance, Scheduling};
+use crate::stress::{Interpolation, Rescaling, ResourceBounds, Scenario, Series};
 use crate::visualiser::Visualiser;
-use crate::world::World;
+use crate::world::{Neighbourhood, Topology, World};
 use csv::Writer;
 use rayon::prelude::*;
-use clap::Parser;
+use clap::{Parser, ValueEnum};
 use std::fmt::Display;
 use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
 // These are constant across simulations
 const SIZE: usize = 50;
@@ -20,9 +44,34 @@ const SETTLEMENTS: usize = 10;
 const HOUSHOLDS: usize = 100;
 const ITERATIONS: u32 = 10000;
 
+// the defaults of invasion experiments
+const TRIALS: u32 = 100;
+const INVASION_ITERATION: u32 = 100;
+const INVASION_LENGTH: u32 = 1000;
+const MUTANTS: usize = 5;
+
 const BIRTH_RATE: f64 = 0.015;
 const DEATH_RATE: f64 = 0.01;
 
+const SETTLEMENT_CAPACITY: f64 = 500.0;
+const MATE_CANDIDATES: usize = 5;
+// the number of requests a household remembers the outcome of
+const MEMORY: usize = 10;
+// the change in a household's image when it helps or refuses
+const IMAGE_STEP: f64 = 0.2;
+const LAND_RADIUS: f64 = 10.0;
+
+// the initial mortality and its rate of growth with age for the Gompertz law
+const GOMPERTZ_INITIAL: f64 = 0.0001;
+const GOMPERTZ_GROWTH: f64 = 0.05;
+
+// the age groups of the age pyramid, the last of which includes all older households
+const AGE_GROUP_WIDTH: u32 = 10;
+const AGE_GROUPS: u32 = 11;
+
+// the number of bins in the histogram of each trait
+const GENE_BINS: usize = 10;
+
 // TODO: These aren't used at the moment
 const years_per_move: u32 = 100;
 const beta: f64 = 1.5;
@@ -33,9 +82,56 @@ const m: f64 = 0.005;
 pub struct Settings {
     pub f: f64,
     pub degradation: f64,
+    pub birth_rate: f64,
+    pub death_rate: f64,
+    pub mortality: Mortality,
+    pub gompertz: (f64, f64),
+    // the minimum age at which a household can reproduce
+    pub maturity: u32,
+    pub reproduction: Reproduction,
+    // the population at which a settlement stops reproducing
+    pub settlement_capacity: f64,
+    // the distance around a settlement that counts as its land
+    pub land_radius: f64,
+    // the population at which the world stops reproducing, or 0 for no limit
+    pub population_cap: usize,
+    pub sharing: Sharing,
+    // the order in which households make requests and are asked to donate
+    pub request_order: Scheduling,
+    pub donor_order: Scheduling,
+    pub update: Update,
+    // the number of interactions a household remembers
+    pub memory: usize,
+    // the change in a household's image when it helps or refuses
+    pub image_step: f64,
+    // the fraction of load that is forgotten each iteration
+    pub load_decay: f64,
+    // the fraction of its resources a household uses to repay its debts each iteration
+    pub repayment: f64,
+    pub resource_inheritance: ResourceInheritance,
+    pub land_inheritance: LandInheritance,
+    pub mate_selection: MateSelection,
+    // the number of households an assortative mate is chosen from
+    pub mate_candidates: usize,
+    pub residence: Residence,
+    // the probability that a newborn follows the residence rule instead of staying with its parent
+    pub dispersal: f64,
+    // the number of iterations between samples of the slower measures
+    pub sample_interval: u32,
+    // the number of bins in the sampled histogram of each trait
+    pub gene_bins: usize,
+    // whether every birth is recorded and exported
+    pub genealogy: bool,
+    pub heredity: Heredity,
     pub title: String,
     pub path: String,
     pub genes: GeneSettings,
+    pub initial_genes: InitialGenes,
+    pub topology: Topology,
+    pub scenario: Scenario,
+    pub bounds: ResourceBounds,
+    pub schedule: Schedule,
+    pub events: Vec<Event>,
 }
 
 impl Settings {
@@ -43,11 +139,168 @@ impl Settings {
         Settings {
             f,
             degradation,
+            birth_rate: BIRTH_RATE,
+            death_rate: DEATH_RATE,
+            mortality: Mortality::Constant,
+            gompertz: (GOMPERTZ_INITIAL, GOMPERTZ_GROWTH),
+            maturity: 0,
+            reproduction: Reproduction::Hunger,
+            settlement_capacity: SETTLEMENT_CAPACITY,
+            land_radius: LAND_RADIUS,
+            population_cap: SIZE.pow(2),
+            sharing: Sharing::AllOrNothing,
+            request_order: Scheduling::Sequential,
+            donor_order: Scheduling::Sequential,
+            update: Update::Asynchronous,
+            memory: MEMORY,
+            image_step: IMAGE_STEP,
+            load_decay: 0.0,
+            repayment: 0.0,
+            resource_inheritance: ResourceInheritance::None,
+            land_inheritance: LandInheritance::Release,
+            mate_selection: MateSelection::Status,
+            mate_candidates: MATE_CANDIDATES,
+            residence: Residence::Parent,
+            dispersal: 1.0,
+            sample_interval: 100,
+            gene_bins: GENE_BINS,
+            genealogy: false,
+            heredity: Heredity::default(),
             title,
             path,
             genes,
+            // custom initial genes are read by Args::configure
+            initial_genes: match genes {
+                GeneSettings::Altruistic => InitialGenes::uniform(Strategy::Altruistic),
+                GeneSettings::Defective => InitialGenes::uniform(Strategy::Defective),
+                GeneSettings::Split | GeneSettings::Custom => InitialGenes::split(),
+            },
+            topology: Topology::default(),
+            scenario: Scenario::Sinusoid,
+            bounds: ResourceBounds::default(),
+            schedule: Schedule::default(),
+            events: Vec::new(),
         }
     }
+
+    // the settings that are recorded alongside the results
+    fn metadata(&self) -> Vec<(&'static str, String)> {
+        vec![
+            ("Title", self.title.clone()),
+            ("Frequency", self.f.to_string()),
+            ("Degradation", self.degradation.to_string()),
+            ("BirthRate", self.birth_rate.to_string()),
+            ("DeathRate", self.death_rate.to_string()),
+            (
+                "Mortality",
+                self.mortality.to_possible_value().unwrap().get_name().to_string(),
+            ),
+            ("Gompertz", format!("{},{}", self.gompertz.0, self.gompertz.1)),
+            ("Maturity", self.maturity.to_string()),
+            (
+                "Reproduction",
+                self.reproduction.to_possible_value().unwrap().get_name().to_string(),
+            ),
+            ("SettlementCapacity", self.settlement_capacity.to_string()),
+            ("LandRadius", self.land_radius.to_string()),
+            ("PopulationCap", self.population_cap.to_string()),
+            (
+                "Sharing",
+                self.sharing.to_possible_value().unwrap().get_name().to_string(),
+            ),
+            (
+                "RequestOrder",
+                self.request_order.to_possible_value().unwrap().get_name().to_string(),
+            ),
+            (
+                "DonorOrder",
+                self.donor_order.to_possible_value().unwrap().get_name().to_string(),
+            ),
+            (
+                "Update",
+                self.update.to_possible_value().unwrap().get_name().to_string(),
+            ),
+            ("Memory", self.memory.to_string()),
+            ("ImageStep", self.image_step.to_string()),
+            ("LoadDecay", self.load_decay.to_string()),
+            ("Repayment", self.repayment.to_string()),
+            (
+                "ResourceInheritance",
+                self.resource_inheritance
+                    .to_possible_value()
+                    .unwrap()
+                    .get_name()
+                    .to_string(),
+            ),
+            (
+                "LandInheritance",
+                self.land_inheritance
+                    .to_possible_value()
+                    .unwrap()
+                    .get_name()
+                    .to_string(),
+            ),
+            (
+                "MateSelection",
+                self.mate_selection.to_possible_value().unwrap().get_name().to_string(),
+            ),
+            ("MateCandidates", self.mate_candidates.to_string()),
+            (
+                "Residence",
+                self.residence.to_possible_value().unwrap().get_name().to_string(),
+            ),
+            ("Dispersal", self.dispersal.to_string()),
+            ("SampleInterval", self.sample_interval.to_string()),
+            ("GeneBins", self.gene_bins.to_string()),
+            ("Genes", self.genes.to_string()),
+            ("InitialGenes", self.initial_genes.to_string()),
+            (
+                "GeneInheritance",
+                self.heredity
+                    .inheritance
+                    .to_possible_value()
+                    .unwrap()
+                    .get_name()
+                    .to_string(),
+            ),
+            (
+                "MutationKernel",
+                self.heredity.kernel.to_possible_value().unwrap().get_name().to_string(),
+            ),
+            (
+                "MutationBounds",
+                self.heredity.bounds.to_possible_value().unwrap().get_name().to_string(),
+            ),
+            (
+                "Mutation",
+                Trait::ALL
+                    .iter()
+                    .zip(self.heredity.mutation)
+                    .map(|(t, mutation)| {
+                        format!(
+                            "{}={},{}",
+                            t.to_possible_value().unwrap().get_name(),
+                            mutation.frequency,
+                            mutation.amplitude
+                        )
+                    })
+                    .collect::<Vec<_>>()
+                    .join("; "),
+            ),
+            ("Topology", self.topology.to_string()),
+            ("Stress", self.scenario.to_string()),
+            ("ResourceBounds", self.bounds.to_string()),
+            ("Schedule", self.schedule.to_string()),
+            (
+                "Events",
+                self.events
+                    .iter()
+                    .map(|e| e.to_string())
+                    .collect::<Vec<_>>()
+                    .join("; "),
+            ),
+        ]
+    }
 }
 
 #[derive(Clone, Copy)]
@@ -55,6 +308,8 @@ pub enum GeneSettings {
     Altruistic,
     Defective,
     Split,
+    // read from a file given by --initial-genes
+    Custom,
 }
 
 impl Display for GeneSettings {
@@ -66,6 +321,7 @@ impl Display for GeneSettings {
                 GeneSettings::Altruistic => 'A',
                 GeneSettings::Defective => 'D',
                 GeneSettings::Split => 'S',
+                GeneSettings::Custom => 'C',
             }
         )
     }
@@ -86,36 +342,362 @@ struct Args {
     /// Whether to purge previous results
     #[arg(short, long, default_value_t = false)]
     purge_results: bool,
+
+    /// Whether to run invasion experiments, where mutants of the opposite strategy are introduced into altruistic and defective populations, instead of the usual simulations
+    #[arg(long, default_value_t = false, conflicts_with = "initial_genes")]
+    invasion: bool,
+
+    /// The number of trials of each invasion experiment
+    #[arg(long, default_value_t = TRIALS)]
+    trials: u32,
+
+    /// The iteration at which mutants are introduced
+    #[arg(long, default_value_t = INVASION_ITERATION)]
+    invasion_iteration: u32,
+
+    /// The number of iterations a trial lasts after mutants are introduced
+    #[arg(long, default_value_t = INVASION_LENGTH)]
+    invasion_length: u32,
+
+    /// The number of mutants introduced
+    #[arg(long, default_value_t = MUTANTS)]
+    mutants: usize,
+
+    /// The id of the settlement mutants are introduced into, or a random settlement if there is none
+    #[arg(long)]
+    invasion_settlement: Option<u32>,
+
+    /// The cells considered adjacent when claiming land
+    #[arg(short, long, value_enum, default_value_t = Neighbourhood::VonNeumann)]
+    neighbourhood: Neighbourhood,
+
+    /// Whether opposite edges of the world are joined
+    #[arg(short, long, default_value_t = false)]
+    toroidal: bool,
+
+    /// The environmental regime: sinusoid, constant:LEVEL, step:BEFORE,AFTER,AT, trend:START,END, ar1:MEAN,PHI,SIGMA, random-walk:START,SIGMA or drought:LEVEL,RATE,SEVERITY,DURATION
+    #[arg(long, default_value = "sinusoid")]
+    stress: Scenario,
+
+    /// A CSV file of yearly environmental values to use instead of the stress regime
+    #[arg(long)]
+    climate: Option<PathBuf>,
+
+    /// How environmental values between recorded years are estimated
+    #[arg(long, value_enum, default_value_t = Interpolation::Linear)]
+    interpolation: Interpolation,
+
+    /// How environmental values are mapped to between 0 and 1
+    #[arg(long, value_enum, default_value_t = Rescaling::MinMax)]
+    rescaling: Rescaling,
+
+    /// The number of years of environmental values that pass each iteration
+    #[arg(long, default_value_t = 1.0, value_parser = positive)]
+    years_per_iteration: f64,
+
+    /// The range of the minimum and maximum resources produced by a patch
+    #[arg(long, default_value = "0,0.6,0.4,1", value_name = "MIN_LOW,MIN_HIGH,MAX_LOW,MAX_HIGH")]
+    resource_bounds: ResourceBounds,
+
+    /// A CSV file of parameter changes at given iterations
+    #[arg(long)]
+    schedule: Option<PathBuf>,
+
+    /// A CSV file of interventions at given iterations
+    #[arg(long)]
+    events: Option<PathBuf>,
+
+    /// How the probability of a household dying is determined
+    #[arg(long, value_enum, default_value_t = Mortality::Constant)]
+    mortality: Mortality,
+
+    /// The mortality of a newborn household under the Gompertz law
+    #[arg(long, default_value_t = GOMPERTZ_INITIAL)]
+    gompertz_initial: f64,
+
+    /// The rate at which mortality grows with age under the Gompertz law
+    #[arg(long, default_value_t = GOMPERTZ_GROWTH)]
+    gompertz_growth: f64,
+
+    /// The minimum age at which a household can reproduce
+    #[arg(long, default_value_t = 0)]
+    maturity: u32,
+
+    /// What the probability of a household reproducing depends on, besides its hunger
+    #[arg(long, value_enum, default_value_t = Reproduction::Hunger)]
+    reproduction: Reproduction,
+
+    /// The population at which a settlement stops reproducing
+    #[arg(long, default_value_t = SETTLEMENT_CAPACITY)]
+    settlement_capacity: f64,
+
+    /// The distance around a settlement that counts as its land
+    #[arg(long, default_value_t = LAND_RADIUS)]
+    land_radius: f64,
+
+    /// The population at which the world stops reproducing, or 0 for no limit
+    #[arg(long, default_value_t = SIZE.pow(2))]
+    population_cap: usize,
+
+    /// How households respond to a request for resources
+    #[arg(long, value_enum, default_value_t = Sharing::AllOrNothing)]
+    sharing: Sharing,
+
+    /// The order in which households make requests
+    #[arg(long, value_enum, default_value_t = Scheduling::Sequential)]
+    request_order: Scheduling,
+
+    /// The order in which households are asked to donate, within their superiors, peers and subordinates
+    #[arg(long, value_enum, default_value_t = Scheduling::Sequential)]
+    donor_order: Scheduling,
+
+    /// When donations take effect
+    #[arg(long, value_enum, default_value_t = Update::Asynchronous)]
+    update: Update,
+
+    /// The number of households a household remembers helping or refusing it, which reciprocators use to decide whether to donate
+    #[arg(long, default_value_t = MEMORY)]
+    memory: usize,
+
+    /// The change in a household's image, from -1 to 1, when it helps or refuses a request it could afford
+    #[arg(long, default_value_t = IMAGE_STEP)]
+    image_step: f64,
+
+    /// The fraction of load, and of the debts behind it, that is forgotten each iteration
+    #[arg(long, default_value_t = 0.0)]
+    load_decay: f64,
+
+    /// The fraction of its resources a household uses to repay the households it owes each iteration
+    #[arg(long, default_value_t = 0.0)]
+    repayment: f64,
+
+    /// Who inherits the resources of a household when it dies
+    #[arg(long, value_enum, default_value_t = ResourceInheritance::None)]
+    resource_inheritance: ResourceInheritance,
+
+    /// Who inherits the resource patch of a household when it dies
+    #[arg(long, value_enum, default_value_t = LandInheritance::Release)]
+    land_inheritance: LandInheritance,
+
+    /// How a household chooses a partner to reproduce with
+    #[arg(long, value_enum, default_value_t = MateSelection::Status)]
+    mate_selection: MateSelection,
+
+    /// The number of households an assortative mate is chosen from
+    #[arg(long, default_value_t = MATE_CANDIDATES)]
+    mate_candidates: usize,
+
+    /// Where a newborn household lives
+    #[arg(long, value_enum, default_value_t = Residence::Parent)]
+    residence: Residence,
+
+    /// The probability that a newborn follows the residence rule instead of staying with its parent
+    #[arg(long, default_value_t = 1.0)]
+    dispersal: f64,
+
+    /// The number of iterations between samples of the demography and genes
+    #[arg(long, default_value_t = 100, value_parser = clap::value_parser!(u32).range(1..))]
+    sample_interval: u32,
+
+    /// The number of bins in the sampled histogram of each trait
+    #[arg(long, default_value_t = GENE_BINS, value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+    gene_bins: usize,
+
+    /// Whether to export the genealogy of every simulation
+    #[arg(long, default_value_t = false)]
+    genealogy: bool,
+
+    /// A CSV file of the genes of the initial households to use instead of the altruistic, defective and split populations
+    #[arg(long)]
+    initial_genes: Option<PathBuf>,
+
+    /// Where a child's genes come from
+    #[arg(long, value_enum, default_value_t = GeneInheritance::RandomParent)]
+    gene_inheritance: GeneInheritance,
+
+    /// The distribution of the change in a trait when it mutates
+    #[arg(long, value_enum, default_value_t = MutationKernel::Uniform)]
+    mutation_kernel: MutationKernel,
+
+    /// What happens to a trait that mutates beyond 0 or 1
+    #[arg(long, value_enum, default_value_t = MutationBounds::Clamp)]
+    mutation_bounds: MutationBounds,
+
+    /// How often and how far a trait mutates when inherited, which can be given once per trait: peer-transfer, subordinate-transfer, attachment, request-threshold, donation-fraction, reciprocity or discrimination
+    #[arg(long, value_name = "TRAIT=FREQUENCY,AMPLITUDE")]
+    mutation: Vec<TraitMutation>,
+}
+
+impl Args {
+    // applies the options shared by every simulation
+    fn configure(&self, mut settings: Settings) -> Result<Settings, csv::Error> {
+        settings.topology = Topology::new(self.neighbourhood, self.toroidal);
+        settings.bounds = self.resource_bounds;
+        settings.scenario = self.stress.clone();
+        settings.mortality = self.mortality;
+        settings.gompertz = (self.gompertz_initial, self.gompertz_growth);
+        settings.maturity = self.maturity;
+        settings.reproduction = self.reproduction;
+        settings.settlement_capacity = self.settlement_capacity;
+        settings.land_radius = self.land_radius;
+        settings.population_cap = self.population_cap;
+        settings.sharing = self.sharing;
+        settings.request_order = self.request_order;
+        settings.donor_order = self.donor_order;
+        settings.update = self.update;
+        settings.memory = self.memory;
+        settings.image_step = self.image_step;
+        settings.load_decay = self.load_decay;
+        settings.repayment = self.repayment;
+        settings.resource_inheritance = self.resource_inheritance;
+        settings.land_inheritance = self.land_inheritance;
+        settings.mate_selection = self.mate_selection;
+        settings.mate_candidates = self.mate_candidates;
+        settings.residence = self.residence;
+        settings.dispersal = self.dispersal;
+        settings.sample_interval = self.sample_interval;
+        settings.gene_bins = self.gene_bins;
+        settings.genealogy = self.genealogy;
+
+        settings.heredity.inheritance = self.gene_inheritance;
+        settings.heredity.kernel = self.mutation_kernel;
+        settings.heredity.bounds = self.mutation_bounds;
+
+        for &TraitMutation(t, mutation) in &self.mutation {
+            settings.heredity.mutation[t as usize] = mutation;
+        }
+
+        if let Some(path) = &self.climate {
+            let series = Series::from_csv(
+                path,
+                self.interpolation,
+                self.rescaling,
+                self.years_per_iteration,
+            )?;
+            settings.scenario = Scenario::Series(Arc::new(series));
+        }
+
+        if let Some(path) = &self.initial_genes {
+            settings.initial_genes = InitialGenes::from_csv(path)?;
+        }
+
+        if let Some(path) = &self.schedule {
+            settings.schedule = Schedule::from_csv(path)?;
+        }
+
+        if let Some(path) = &self.events {
+            settings.events = Event::from_csv(path)?;
+        }
+
+        Ok(settings)
+    }
+
+    fn invasion(&self) -> Invasion {
+        Invasion {
+            trials: self.trials,
+            iteration: self.invasion_iteration,
+            length: self.invasion_length,
+            mutants: self.mutants,
+            settlement: self.invasion_settlement,
+        }
+    }
+}
+
+// parses a number that must be finite and above 0
+fn positive(s: &str) -> Result<f64, String> {
+    let value: f64 = s.trim().parse().map_err(|e| format!("{e}"))?;
+
+    if value > 0.0 && value.is_finite() {
+        Ok(value)
+    } else {
+        Err(String::from("must be finite and above 0"))
+    }
 }
 
 fn main() {
     let args = Args::parse();
 
     if args.visualise {
-        visualise(Settings::new(
+        let settings = args.configure(Settings::new(
             256.0,
             0.25,
             String::new(),
             String::new(),
             GeneSettings::Altruistic,
         ));
+
+        match settings {
+            Ok(settings) => visualise(settings),
+            Err(e) => eprintln!("CSV Error: {e}"),
+        }
     } else {
-        let settings = generate_settings(args.release_resolution, args.purge_results);
-        let results: Vec<_> = settings.into_par_iter().map(run).collect();
+        // invasions start from populations fixed on one strategy,
+        //   and custom initial genes replace the usual populations
+        let populations = if args.invasion {
+            vec![GeneSettings::Altruistic, GeneSettings::Defective]
+        } else if args.initial_genes.is_some() {
+            vec![GeneSettings::Custom]
+        } else {
+            vec![
+                GeneSettings::Split,
+                GeneSettings::Altruistic,
+                GeneSettings::Defective,
+            ]
+        };
+
+        let settings: Result<Vec<_>, _> =
+            generate_settings(args.release_resolution, args.purge_results, &populations)
+                .into_iter()
+                .map(|s| args.configure(s))
+                .collect();
 
-        for result in results {
-            if let Err(RunError::CSVError(e)) = &result {
+        let settings = match settings {
+            Ok(settings) => settings,
+            Err(e) => {
                 eprintln!("CSV Error: {e}");
+                return;
+            }
+        };
+
+        if args.invasion {
+            let invasion = args.invasion();
+            let results: Vec<_> = settings
+                .into_par_iter()
+                .map(|s| invade(s, &invasion))
+                .collect();
+
+            let mut summaries = Vec::new();
+            for result in results {
+                match result {
+                    Ok(summary) => summaries.push(summary),
+                    Err(e) => report(e),
+                }
+            }
+
+            if let Err(e) = summarise(summaries) {
+                report(e);
             }
+        } else {
+            let results: Vec<_> = settings.into_par_iter().map(run).collect();
 
-            if let Err(RunError::FlushError(e)) = result {
-                eprintln!("Flush Error: {e}");
+            for result in results {
+                if let Err(e) = result {
+                    report(e);
+                }
             }
         }
     }
 }
 
-fn generate_settings(release: bool, purge: bool) -> Vec<Settings> {
+fn report(error: RunError) {
+    match error {
+        RunError::CSVError(e) => eprintln!("CSV Error: {e}"),
+        RunError::FlushError(e) => eprintln!("Flush Error: {e}"),
+        RunError::IoError(e) => eprintln!("IO Error: {e}"),
+    }
+}
+
+fn generate_settings(release: bool, purge: bool, populations: &[GeneSettings]) -> Vec<Settings> {
     let mut settings = vec![];
 
     if purge {
@@ -123,11 +705,7 @@ fn generate_settings(release: bool, purge: bool) -> Vec<Settings> {
         fs::create_dir("results").unwrap();
     }
 
-    for genes in [
-        GeneSettings::Split,
-        GeneSettings::Altruistic,
-        GeneSettings::Defective,
-    ] {
+    for &genes in populations {
         let folder = format!("results/{}", genes);
         fs::create_dir(&folder).unwrap();
 
@@ -184,57 +762,368 @@ fn visualise(settings: Settings) {
     }
 }
 
+// the settings are written next to the results so that runs can be compared
+fn write_metadata(path: &str, settings: Vec<(&'static str, String)>) -> Result<(), RunError> {
+    let mut metadata = Writer::from_path(Path::new(path).with_extension("meta.csv"))
+        .map_err(RunError::CSVError)?;
+    metadata
+        .write_record(["Setting", "Value"])
+        .map_err(RunError::CSVError)?;
+    for (setting, value) in settings {
+        metadata
+            .write_record([setting, &value])
+            .map_err(RunError::CSVError)?;
+    }
+    metadata.flush().map_err(RunError::FlushError)
+}
+
 fn run(settings: Settings) -> Result<(), RunError> {
     let title = settings.title.clone();
-    let mut writer = Writer::from_path(&settings.path).map_err(RunError::CSVError)?;
+    let path = settings.path.clone();
 
-    writer
-        .write_record(&[
+    write_metadata(&path, settings.metadata())?;
+
+    let mut writer = Writer::from_path(&path).map_err(RunError::CSVError)?;
+
+    let mut header = vec![
+        "Iteration",
+        "Settlements",
+        "Population",
+        "AveResources",
+        "MaxResources",
+        "MaxLoad",
+        "Egalitarianism",
+        "Requests",
+        "Donations",
+        "Transferred",
+        "Repaid",
+        "Debt",
+        "AveImage",
+        "Environment",
+        "Degradation",
+        "BirthRate",
+        "DeathRate",
+        "BackgroundDeaths",
+        "StarvationDeaths",
+        "SenescenceDeaths",
+        "Births",
+        "AveAge",
+        "Dispersals",
+        "AltruisticLineages",
+        "DefectiveLineages",
+    ];
+    // the average of each trait follows the general measures
+    header.splice(6..6, Trait::ALL.iter().map(|t| t.name()));
+
+    writer.write_record(header).map_err(RunError::CSVError)?;
+
+    // the demography is sampled, with births and deaths counted since the last sample
+    let mut demography = Writer::from_path(Path::new(&path).with_extension("demography.csv"))
+        .map_err(RunError::CSVError)?;
+    let mut header = vec![
+        String::from("Iteration"),
+        String::from("Population"),
+        String::from("AveAge"),
+        String::from("Births"),
+        String::from("Deaths"),
+        String::from("GenerationTime"),
+    ];
+    for group in 0..AGE_GROUPS {
+        let start = group * AGE_GROUP_WIDTH;
+        header.push(if group == AGE_GROUPS - 1 {
+            format!("Age{start}+")
+        } else {
+            format!("Age{start}-{}", start + AGE_GROUP_WIDTH - 1)
+        });
+    }
+    demography
+        .write_record(header)
+        .map_err(RunError::CSVError)?;
+
+    // the distribution of each trait is sampled alongside the demography
+    let mut genes = Writer::from_path(Path::new(&path).with_extension("genes.csv"))
+        .map_err(RunError::CSVError)?;
+    let bins = settings.gene_bins;
+    let mut header = vec![
+        String::from("Iteration"),
+        String::from("Trait"),
+        String::from("Mean"),
+        String::from("Variance"),
+        String::from("Bimodality"),
+    ];
+    for bin in 0..bins {
+        header.push(format!(
+            "Bin{}-{}",
+            bin as f64 / bins as f64,
+            (bin + 1) as f64 / bins as f64
+        ));
+    }
+    genes.write_record(header).map_err(RunError::CSVError)?;
+
+    // the change in cooperation between samples is split into its causes
+    let mut price = Writer::from_path(Path::new(&path).with_extension("price.csv"))
+        .map_err(RunError::CSVError)?;
+    price
+        .write_record([
             "Iteration",
-            "Settlements",
-            "Population",
-            "AveResources",
-            "MaxResources",
-            "MaxLoad",
-            "PeerTransfer",
-            "SubTransfer",
-            "Egalitarianism",
+            "Start",
+            "Change",
+            "BetweenSettlements",
+            "WithinSettlements",
+            "Transmission",
         ])
         .map_err(RunError::CSVError)?;
+    let mut snapshot: Option<Snapshot> = None;
+
+    let (mut births, mut deaths, mut parent_ages) = (0, 0, 0);
 
     // TODO: take averages of three different worlds
+    let sample_interval = settings.sample_interval;
     let mut world = World::new(settings);
     for i in 0..ITERATIONS {
-        let (peer, subordinate) = world.cooperation();
+        let (altruistic, defective) = world.count_lineages();
 
-        let fields: Vec<Box<dyn Display>> = vec![
+        let mut fields: Vec<Box<dyn Display>> = vec![
             Box::new(world.iteration()),
             Box::new(world.count_settlements()),
             Box::new(world.count_population()),
             Box::new(world.average_resources()),
             Box::new(world.max_resources()),
             Box::new(world.max_load()),
-            Box::new(peer),
-            Box::new(subordinate),
             Box::new(world.egalitarianism()),
+            Box::new(world.count_requests()),
+            Box::new(world.count_donations()),
+            Box::new(world.transferred()),
+            Box::new(world.repaid()),
+            Box::new(world.total_debt()),
+            Box::new(world.average_image()),
+            Box::new(world.environment()),
+            Box::new(world.settings().degradation),
+            Box::new(world.settings().birth_rate),
+            Box::new(world.settings().death_rate),
+            Box::new(world.count_deaths(Cause::Background)),
+            Box::new(world.count_deaths(Cause::Starvation)),
+            Box::new(world.count_deaths(Cause::Senescence)),
+            Box::new(world.count_births()),
+            Box::new(world.average_age()),
+            Box::new(world.count_dispersals()),
+            Box::new(altruistic),
+            Box::new(defective),
         ];
+        fields.splice(
+            6..6,
+            Trait::ALL
+                .iter()
+                .map(|&t| Box::new(world.average_trait(t)) as Box<dyn Display>),
+        );
 
         writer
             .write_record(fields.iter().map(|f| f.to_string()).collect::<Vec<_>>())
             .map_err(RunError::CSVError)?;
 
+        if i % sample_interval == 0 {
+            let mut fields = vec![
+                world.iteration().to_string(),
+                world.count_population().to_string(),
+                world.average_age().to_string(),
+                births.to_string(),
+                deaths.to_string(),
+                // the generation time is the average age of parents when they reproduce
+                (parent_ages as f64 / births as f64).to_string(),
+            ];
+            fields.extend(
+                world
+                    .age_pyramid(AGE_GROUP_WIDTH, AGE_GROUPS)
+                    .iter()
+                    .map(|n| n.to_string()),
+            );
+
+            demography
+                .write_record(fields)
+                .map_err(RunError::CSVError)?;
+
+            for t in Trait::ALL {
+                let values = world.traits(t);
+
+                let mut fields = vec![
+                    world.iteration().to_string(),
+                    t.name().to_string(),
+                    statistics::mean(&values).to_string(),
+                    statistics::variance(&values).to_string(),
+                    statistics::bimodality(&values).to_string(),
+                ];
+                fields.extend(
+                    statistics::histogram(&values, bins)
+                        .iter()
+                        .map(|n| n.to_string()),
+                );
+
+                genes.write_record(fields).map_err(RunError::CSVError)?;
+            }
+
+            if let Some(snapshot) = &snapshot {
+                let mut fields = vec![world.iteration().to_string()];
+                fields.extend(Price::new(snapshot, &world).fields());
+                price.write_record(fields).map_err(RunError::CSVError)?;
+            }
+            snapshot = Some(Snapshot::new(&world));
+            world.mark_ancestors();
+
+            (births, deaths, parent_ages) = (0, 0, 0);
+        }
+
         world.iterate();
 
+        births += world.count_births();
+        deaths += world.count_deaths(Cause::Background)
+            + world.count_deaths(Cause::Starvation)
+            + world.count_deaths(Cause::Senescence);
+        parent_ages += world.parent_ages() as u64;
+
         if i % 100 == 0 {
             println!("Iteration {i} of {} completed!", title);
         }
     }
 
     writer.flush().map_err(RunError::FlushError)?;
+    demography.flush().map_err(RunError::FlushError)?;
+    genes.flush().map_err(RunError::FlushError)?;
+    price.flush().map_err(RunError::FlushError)?;
+
+    // the interventions are logged separately as they happen rarely
+    if !world.settings().events.is_empty() {
+        let mut log = Writer::from_path(Path::new(&path).with_extension("events.csv"))
+            .map_err(RunError::CSVError)?;
+        log.write_record(["Iteration", "Event", "Settlement", "Households", "Resources"])
+            .map_err(RunError::CSVError)?;
+        for record in world.event_log() {
+            log.write_record(record.fields()).map_err(RunError::CSVError)?;
+        }
+        log.flush().map_err(RunError::FlushError)?;
+    }
+
+    if world.settings().genealogy {
+        let mut births = Writer::from_path(Path::new(&path).with_extension("genealogy.csv"))
+            .map_err(RunError::CSVError)?;
+        births
+            .write_record(["Iteration", "Child", "Parent", "Mate", "Settlement"])
+            .map_err(RunError::CSVError)?;
+        for birth in world.genealogy() {
+            births
+                .write_record(birth.fields())
+                .map_err(RunError::CSVError)?;
+        }
+        births.flush().map_err(RunError::FlushError)?;
+
+        let trees = genealogy::newick(world.genealogy(), world.count_founders() as u32);
+        fs::write(Path::new(&path).with_extension("nwk"), trees).map_err(RunError::IoError)?;
+    }
+
     Ok(())
 }
 
+
+// runs the trials of an invasion experiment, returning a summary of them
+fn invade(settings: Settings, invasion: &Invasion) -> Result<Vec<String>, RunError> {
+    let path = settings.path.clone();
+
+    let mut metadata = settings.metadata();
+    metadata.extend(invasion.metadata());
+    write_metadata(&path, metadata)?;
+
+    // the mutants take the opposite strategy to the residents
+    let (resident, mutant) = match settings.genes {
+        GeneSettings::Defective => (Genes::defective(), Genes::altruistic()),
+        _ => (Genes::altruistic(), Genes::defective()),
+    };
+
+    let mut writer = Writer::from_path(Path::new(&path).with_extension("invasion.csv"))
+        .map_err(RunError::CSVError)?;
+    writer
+        .write_record([
+            "Trial",
+            "Mutants",
+            "InitialFrequency",
+            "Iteration",
+            "Population",
+            "MutantFrequency",
+            "AveCooperation",
+            "Outcome",
+        ])
+        .map_err(RunError::CSVError)?;
+
+    let mut trials = Vec::new();
+    for t in 0..invasion.trials {
+        let trial = invasion.trial(&settings, resident, mutant);
+
+        let mut fields = vec![t.to_string()];
+        fields.extend(trial.fields());
+        writer.write_record(fields).map_err(RunError::CSVError)?;
+
+        trials.push(trial);
+
+        if t % 10 == 0 {
+            println!("Trial {t} of {} completed!", settings.title);
+        }
+    }
+
+    writer.flush().map_err(RunError::FlushError)?;
+
+    let n = trials.len() as f64;
+    let probability = |outcome| trials.iter().filter(|t| t.outcome == outcome).count() as f64 / n;
+
+    // the averages are only over the trials in which the population survived
+    let survived: Vec<&Trial> = trials
+        .iter()
+        .filter(|t| t.outcome != Outcome::Collapse)
+        .collect();
+    let average = |value: fn(&Trial) -> f64| {
+        survived.iter().map(|&t| value(t)).sum::<f64>() / survived.len() as f64
+    };
+
+    Ok(vec![
+        settings.title.clone(),
+        settings.genes.to_string(),
+        settings.f.to_string(),
+        settings.degradation.to_string(),
+        trials.len().to_string(),
+        (trials.iter().filter(|t| t.invaded()).count() as f64 / n).to_string(),
+        probability(Outcome::Fixation).to_string(),
+        probability(Outcome::Extinction).to_string(),
+        probability(Outcome::Collapse).to_string(),
+        average(|t| t.frequency).to_string(),
+        average(|t| t.cooperation).to_string(),
+    ])
+}
+
+// the invasion experiments are summarised together, one per parameter combination
+fn summarise(summaries: Vec<Vec<String>>) -> Result<(), RunError> {
+    let mut writer = Writer::from_path("results/invasion.csv").map_err(RunError::CSVError)?;
+    writer
+        .write_record([
+            "Title",
+            "Residents",
+            "Frequency",
+            "Degradation",
+            "Trials",
+            "Invasion",
+            "Fixation",
+            "Extinction",
+            "Collapse",
+            "AveMutantFrequency",
+            "AveCooperation",
+        ])
+        .map_err(RunError::CSVError)?;
+
+    for summary in summaries {
+        writer.write_record(summary).map_err(RunError::CSVError)?;
+    }
+
+    writer.flush().map_err(RunError::FlushError)
+}
+
 enum RunError {
     CSVError(csv::Error),
     FlushError(std::io::Error),
+    IoError(std::io::Error),
 }
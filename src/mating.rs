@@ -0,0 +1,266 @@
+use crate::household::Household;
+use crate::settlement::Settlement;
+use crate::Settings;
+use clap::ValueEnum;
+use rand::{rngs::ThreadRng, seq::index::sample, Rng};
+
+/// How a household chooses a partner to reproduce with
+#[derive(Clone, Copy, ValueEnum)]
+pub enum MateSelection {
+    /// A household from an influential settlement, more likely the higher its load
+    Status,
+    /// A random household from the same settlement
+    Random,
+    /// The household with the closest status from a sample of the same settlement
+    AssortativeStatus,
+    /// The household with the most similar genes from a sample of the same settlement
+    AssortativeGenes,
+}
+
+// the index of the partner's settlement and the partner,
+//   or none if the household reproduces alone
+pub fn choose_partner(
+    settlements: &[Settlement],
+    n: usize,
+    i: usize,
+    settings: &Settings,
+    rng: &mut ThreadRng,
+) -> Option<(usize, usize)> {
+    let settlement = &settlements[n];
+    let household = &settlement.households[i];
+
+    match settings.mate_selection {
+        MateSelection::Status => {
+            // the household can't choose itself,
+            //   so its load is left out of its own settlement's share of the wheel
+            let share = |m: usize, s: &Settlement| {
+                if m == n {
+                    f64::max(s.status() - household.load, 0.0)
+                } else {
+                    s.status()
+                }
+            };
+            let excluded = |m: usize| if m == n { Some(i) } else { None };
+
+            let candidates: Vec<_> = settlements
+                .iter()
+                .enumerate()
+                .filter(|(_, s)| settlement.influence(s, &settings.topology) > 0.0)
+                .map(|(m, s)| (m, s, share(m, s)))
+                .collect();
+
+            let total_statuses: f64 = candidates.iter().map(|&(_, _, share)| share).sum();
+            if total_statuses <= 0.0 || !total_statuses.is_finite() {
+                return None;
+            }
+
+            // a roulette wheel over every other household in the candidate settlements,
+            //   where each household takes up as much of the wheel as its load
+            let mut chosen = rng.gen_range(0.0..total_statuses);
+
+            for &(m, s, share) in &candidates {
+                if chosen < share {
+                    return s.find_partner(chosen, excluded(m)).map(|j| (m, j));
+                }
+
+                chosen -= share;
+            }
+
+            // rounding can leave a little of the wheel over,
+            //   which belongs to the last settlement with any share
+            candidates
+                .iter()
+                .rev()
+                .find(|&&(_, _, share)| share > 0.0)
+                .and_then(|&(m, s, share)| s.find_partner(share, excluded(m)).map(|j| (m, j)))
+        }
+        MateSelection::Random => {
+            let others = others(settlement, i);
+            let j = *others.get(rng.gen_range(0..others.len().max(1)))?;
+
+            Some((n, j))
+        }
+        MateSelection::AssortativeStatus => {
+            let status = household.status();
+
+            closest(settlement, i, settings.mate_candidates, rng, |other| {
+                (other.status() - status).abs()
+            })
+            .map(|j| (n, j))
+        }
+        MateSelection::AssortativeGenes => {
+            closest(settlement, i, settings.mate_candidates, rng, |other| {
+                household.genes.distance(&other.genes)
+            })
+            .map(|j| (n, j))
+        }
+    }
+}
+
+// the indices of every other household in the settlement
+fn others(settlement: &Settlement, i: usize) -> Vec<usize> {
+    (0..settlement.population()).filter(|&j| j != i).collect()
+}
+
+// the household from a random sample of the others in the settlement
+//   that is the smallest distance from household i
+fn closest(
+    settlement: &Settlement,
+    i: usize,
+    candidates: usize,
+    rng: &mut ThreadRng,
+    distance: impl Fn(&Household) -> f64,
+) -> Option<usize> {
+    let others = others(settlement, i);
+    let amount = usize::min(candidates, others.len());
+
+    sample(rng, others.len(), amount)
+        .iter()
+        .map(|k| others[k])
+        .min_by(|&a, &b| {
+            distance(&settlement.households[a]).total_cmp(&distance(&settlement.households[b]))
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::genes::Genes;
+    use crate::world::Index;
+    use crate::GeneSettings;
+    use proptest::prelude::*;
+
+    // loads from none at all to close to the largest a float can hold
+    fn load() -> impl Strategy<Value = f64> {
+        prop_oneof![
+            Just(0.0),
+            0.0..1e-300,
+            0.0..1e-6,
+            0.0..10.0,
+            1e100..1e300,
+            Just(f64::MAX),
+        ]
+    }
+
+    // the position of a settlement and the load and strategy of each of its households
+    type Layout = Vec<(usize, usize, Vec<(f64, bool)>)>;
+
+    // settlements of up to a few households each, which may be empty
+    fn layout() -> impl Strategy<Value = Layout> {
+        prop::collection::vec(
+            (
+                0..crate::SIZE,
+                0..crate::SIZE,
+                prop::collection::vec((load(), any::<bool>()), 0..6),
+            ),
+            1..5,
+        )
+    }
+
+    fn build(layout: Layout) -> Vec<Settlement> {
+        let mut first_id = 0;
+
+        layout
+            .into_iter()
+            .enumerate()
+            .map(|(n, (x, y, households))| {
+                let genes = households
+                    .iter()
+                    .map(|&(_, altruistic)| {
+                        if altruistic {
+                            Genes::altruistic()
+                        } else {
+                            Genes::defective()
+                        }
+                    })
+                    .collect();
+
+                let mut settlement = Settlement::new(n as u32, Index(x, y), genes, first_id);
+                first_id += households.len() as u32;
+
+                for (household, &(load, _)) in settlement.households.iter_mut().zip(&households) {
+                    household.load = load;
+                }
+
+                settlement
+            })
+            .collect()
+    }
+
+    proptest! {
+        #[test]
+        fn choose_partner_is_in_range_and_not_self(
+            layout in layout(),
+            focal in any::<prop::sample::Index>(),
+            candidates in 0..10usize,
+        ) {
+            let settlements = build(layout);
+            let occupied: Vec<_> = settlements
+                .iter()
+                .enumerate()
+                .flat_map(|(n, s)| (0..s.population()).map(move |i| (n, i)))
+                .collect();
+            prop_assume!(!occupied.is_empty());
+            let (n, i) = occupied[focal.index(occupied.len())];
+
+            let mut settings = Settings::new(
+                0.0,
+                0.0,
+                String::new(),
+                String::new(),
+                GeneSettings::Altruistic,
+            );
+            settings.mate_candidates = candidates;
+            let mut rng = rand::thread_rng();
+
+            for &selection in MateSelection::value_variants() {
+                settings.mate_selection = selection;
+
+                if let Some((m, j)) = choose_partner(&settlements, n, i, &settings, &mut rng) {
+                    prop_assert!(m < settlements.len());
+                    prop_assert!(j < settlements[m].population());
+                    prop_assert!((m, j) != (n, i));
+                }
+            }
+        }
+
+        #[test]
+        fn find_partner_covers_the_wheel(
+            layout in layout(),
+            position in 0.0..1.0f64,
+        ) {
+            for settlement in &build(layout) {
+                let status = settlement.status();
+
+                if status <= 0.0 {
+                    prop_assert_eq!(settlement.find_partner(0.0, None), None);
+                    continue;
+                }
+
+                // the start, the end and a point in between
+                for x in [0.0, position * status, status] {
+                    let j = settlement.find_partner(x, None);
+                    prop_assert!(j.is_some_and(|j| j < settlement.population()));
+                    prop_assert!(settlement.households[j.unwrap()].load > 0.0);
+                }
+            }
+        }
+
+        #[test]
+        fn find_partner_skips_the_excluded_household(
+            layout in layout(),
+            excluded in any::<prop::sample::Index>(),
+            position in 0.0..1.0f64,
+        ) {
+            for settlement in build(layout).iter().filter(|s| s.population() > 0) {
+                let excluded = excluded.index(settlement.population());
+                let share = settlement.status() - settlement.households[excluded].load;
+
+                if let Some(j) = settlement.find_partner(position * share, Some(excluded)) {
+                    prop_assert!(j < settlement.population());
+                    prop_assert!(j != excluded);
+                }
+            }
+        }
+    }
+}
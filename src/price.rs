@@ -0,0 +1,186 @@
+use crate::world::World;
+use std::collections::HashMap;
+
+// the households at a sample, by id, with the index of their settlement and their cooperation
+pub struct Snapshot {
+    iteration: u32,
+    groups: usize,
+    households: HashMap<u32, (usize, f64)>,
+}
+
+impl Snapshot {
+    pub fn new(world: &World) -> Self {
+        Snapshot {
+            iteration: world.iteration(),
+            groups: world.count_settlements(),
+            households: world
+                .households()
+                .map(|(n, h)| (h.id, (n, h.genes.cooperation())))
+                .collect(),
+        }
+    }
+}
+
+// the change in the average cooperation since a snapshot, split by the Price equation
+//   into selection between settlements, selection within settlements and transmission
+pub struct Price {
+    pub start: u32,
+    pub change: f64,
+    pub between: f64,
+    pub within: f64,
+    pub transmission: f64,
+}
+
+impl Price {
+    // the fitness of each household in the snapshot is the number of households now
+    //   that are it or descend from it, and its offspring cooperation is their average
+    pub fn new(snapshot: &Snapshot, world: &World) -> Self {
+        let mut descendants: HashMap<u32, (f64, f64)> = HashMap::new();
+        for (_, household) in world.households() {
+            if let Some(ancestor) = world.ancestor(household.id) {
+                let entry = descendants.entry(ancestor).or_default();
+                entry.0 += 1.0;
+                entry.1 += household.genes.cooperation();
+            }
+        }
+
+        Self::decompose(snapshot, &descendants)
+    }
+
+    // the decomposition given the number of descendants of each household in the snapshot
+    //   and the total cooperation of those descendants
+    fn decompose(snapshot: &Snapshot, descendants: &HashMap<u32, (f64, f64)>) -> Self {
+        let n = snapshot.households.len() as f64;
+
+        // the size and total fitness and cooperation of each settlement
+        let mut groups = vec![(0.0, 0.0, 0.0); snapshot.groups];
+        let (mut fitness, mut cooperation) = (0.0, 0.0);
+        for (id, &(k, z)) in &snapshot.households {
+            let w = descendants.get(id).map_or(0.0, |&(w, _)| w);
+
+            groups[k].0 += 1.0;
+            groups[k].1 += w;
+            groups[k].2 += z;
+            fitness += w;
+            cooperation += z;
+        }
+
+        let (w_mean, z_mean) = (fitness / n, cooperation / n);
+
+        let between = groups
+            .iter()
+            .filter(|g| g.0 > 0.0)
+            .map(|&(size, w, z)| size * (w / size - w_mean) * (z / size - z_mean))
+            .sum::<f64>()
+            / (n * w_mean);
+
+        let (mut within, mut transmission, mut offspring) = (0.0, 0.0, 0.0);
+        for (id, &(k, z)) in &snapshot.households {
+            let (size, group_w, group_z) = groups[k];
+
+            let (w, total) = descendants.get(id).copied().unwrap_or_default();
+
+            within += (w - group_w / size) * (z - group_z / size);
+            transmission += total - w * z;
+            offspring += total;
+        }
+
+        Price {
+            start: snapshot.iteration,
+            change: offspring / fitness - z_mean,
+            between,
+            within: within / (n * w_mean),
+            transmission: transmission / (n * w_mean),
+        }
+    }
+
+    pub fn fields(&self) -> Vec<String> {
+        vec![
+            self.start.to_string(),
+            self.change.to_string(),
+            self.between.to_string(),
+            self.within.to_string(),
+            self.transmission.to_string(),
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    fn assert_identity(price: &Price) {
+        let sum = price.between + price.within + price.transmission;
+        assert!(
+            (price.change - sum).abs() <= 1e-9 * f64::max(1.0, price.change.abs()),
+            "{} != {} + {} + {}",
+            price.change,
+            price.between,
+            price.within,
+            price.transmission
+        );
+    }
+
+    #[test]
+    fn the_terms_sum_to_the_change() {
+        // two settlements, where the cooperators of the first leave more descendants
+        //   and one of them has less cooperative descendants
+        let snapshot = Snapshot {
+            iteration: 100,
+            groups: 2,
+            households: HashMap::from([
+                (0, (0, 1.0)),
+                (1, (0, 0.8)),
+                (2, (0, 0.0)),
+                (3, (1, 0.2)),
+                (4, (1, 0.0)),
+            ]),
+        };
+        let descendants = HashMap::from([
+            (0, (3.0, 2.4)),
+            (1, (2.0, 1.6)),
+            (2, (1.0, 0.0)),
+            (4, (1.0, 0.1)),
+        ]);
+
+        let price = Price::decompose(&snapshot, &descendants);
+
+        assert_eq!(price.start, 100);
+        assert!((price.change - (4.1 / 7.0 - 0.4)).abs() < 1e-12);
+        assert!(price.between > 0.0);
+        assert!(price.transmission < 0.0);
+        assert_identity(&price);
+    }
+
+    proptest! {
+        #[test]
+        fn the_terms_always_sum_to_the_change(
+            households in prop::collection::vec(
+                (0..4usize, 0.0..=1.0f64, 0..5u32, 0.0..=1.0f64),
+                1..40,
+            ),
+        ) {
+            prop_assume!(households.iter().any(|&(_, _, w, _)| w > 0));
+
+            let snapshot = Snapshot {
+                iteration: 0,
+                groups: 4,
+                households: households
+                    .iter()
+                    .enumerate()
+                    .map(|(id, &(k, z, _, _))| (id as u32, (k, z)))
+                    .collect(),
+            };
+            // each descendant has the cooperation of its ancestor changed by the same amount
+            let descendants = households
+                .iter()
+                .enumerate()
+                .filter(|(_, &(_, _, w, _))| w > 0)
+                .map(|(id, &(_, _, w, z))| (id as u32, (w as f64, w as f64 * z)))
+                .collect();
+
+            assert_identity(&Price::decompose(&snapshot, &descendants));
+        }
+    }
+}
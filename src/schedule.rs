@@ -0,0 +1,167 @@
+use crate::config::{invalid, parse};
+use crate::Settings;
+use clap::ValueEnum;
+use csv::Reader;
+use std::{fmt::Display, path::Path};
+
+/// A parameter that can change during a simulation
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Parameter {
+    Degradation,
+    BirthRate,
+    DeathRate,
+}
+
+impl Parameter {
+    fn get(&self, settings: &Settings) -> f64 {
+        match self {
+            Parameter::Degradation => settings.degradation,
+            Parameter::BirthRate => settings.birth_rate,
+            Parameter::DeathRate => settings.death_rate,
+        }
+    }
+
+    fn set(&self, settings: &mut Settings, value: f64) {
+        match self {
+            Parameter::Degradation => settings.degradation = value,
+            Parameter::BirthRate => settings.birth_rate = value,
+            Parameter::DeathRate => settings.death_rate = value,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum Operation {
+    /// The parameter is replaced by the value
+    Set,
+    /// The parameter is multiplied by the value
+    Scale,
+}
+
+#[derive(Clone)]
+pub struct Change {
+    iteration: u32,
+    // the change is undone at this iteration, if there is one
+    until: Option<u32>,
+    parameter: Parameter,
+    operation: Operation,
+    value: f64,
+}
+
+impl Change {
+    // whether either change happens while the other's window is open
+    fn overlaps(&self, other: &Change) -> bool {
+        let within = |c: &Change, t: u32| c.until.is_some_and(|u| c.iteration <= t && t < u);
+
+        self.parameter == other.parameter
+            && (within(self, other.iteration) || within(other, self.iteration))
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct Schedule {
+    changes: Vec<Change>,
+    // the values to restore parameters to when their window closes
+    reverts: Vec<(u32, Parameter, f64)>,
+}
+
+impl Schedule {
+    /// Reads a CSV file with a header row and the columns
+    ///   iteration, until, parameter, operation and value,
+    ///   where until may be left empty for permanent changes
+    pub fn from_csv(path: impl AsRef<Path>) -> Result<Self, csv::Error> {
+        let mut reader = Reader::from_path(path)?;
+        let mut changes = Vec::new();
+
+        for record in reader.deserialize() {
+            let (iteration, until, parameter, operation, value): (
+                u32,
+                Option<u32>,
+                String,
+                String,
+                f64,
+            ) = record?;
+
+            // the window would close before it opens, so the change would never be undone
+            if until.is_some_and(|until| until <= iteration) {
+                return Err(invalid(format!(
+                    "the window from {iteration} to {} is empty",
+                    until.unwrap()
+                )));
+            }
+
+            let change = Change {
+                iteration,
+                until,
+                parameter: parse(&parameter)?,
+                operation: parse(&operation)?,
+                value,
+            };
+
+            // a window restores the value from when it opened,
+            //   which would undo any other change to the parameter while it is open
+            if let Some(other) = changes.iter().find(|c| change.overlaps(c)) {
+                return Err(invalid(format!(
+                    "the change at {iteration} overlaps the change at {} to the same parameter",
+                    other.iteration
+                )));
+            }
+
+            changes.push(change);
+        }
+
+        Ok(Schedule {
+            changes,
+            reverts: Vec::new(),
+        })
+    }
+
+    /// Applies the changes scheduled for iteration t
+    pub fn apply(&mut self, t: u32, settings: &mut Settings) {
+        // windows are closed first, so a window can be followed immediately by another
+        for &(_, parameter, value) in self.reverts.iter().filter(|r| r.0 == t) {
+            parameter.set(settings, value);
+        }
+        self.reverts.retain(|r| r.0 != t);
+
+        for change in self.changes.iter().filter(|c| c.iteration == t) {
+            let current = change.parameter.get(settings);
+
+            if let Some(until) = change.until {
+                self.reverts.push((until, change.parameter, current));
+            }
+
+            change.parameter.set(
+                settings,
+                match change.operation {
+                    Operation::Set => change.value,
+                    Operation::Scale => current * change.value,
+                },
+            );
+        }
+    }
+}
+
+impl Display for Schedule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let changes: Vec<_> = self
+            .changes
+            .iter()
+            .map(|c| {
+                let window = match c.until {
+                    Some(until) => format!("{}-{}", c.iteration, until),
+                    None => c.iteration.to_string(),
+                };
+
+                format!(
+                    "{window} {} {} {}",
+                    c.parameter.to_possible_value().unwrap().get_name(),
+                    c.operation.to_possible_value().unwrap().get_name(),
+                    c.value
+                )
+            })
+            .collect();
+
+        write!(f, "{}", changes.join("; "))
+    }
+}
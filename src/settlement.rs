@@ -1,7 +1,8 @@
-use crate::household::{Genes, Household, QueryType};
-use crate::world::Index;
-use crate::GeneSettings;
-use rand::{rngs::ThreadRng, Rng};
+use crate::genes::{Genes, Heredity, Trait};
+use crate::household::{Household, QueryType, Sharing};
+use crate::world::{Index, Topology};
+use clap::ValueEnum;
+use rand::{rngs::ThreadRng, seq::SliceRandom, Rng};
 
 pub struct Settlement {
     pub id: u32,         // used for marking land in the matrix
@@ -9,30 +10,21 @@ pub struct Settlement {
     pub households: Vec<Household>,
 }
 
+// the outcome of a request for resources
+pub struct Response {
+    // the index of each household that would donate and how much
+    pub donations: Vec<(usize, f64)>,
+    // the indices of the households that could have donated but refused
+    pub refusals: Vec<usize>,
+}
+
 impl Settlement {
-    pub fn new(
-        id: u32,
-        position: Index,
-        initial_households: usize,
-        initial_genes: GeneSettings,
-    ) -> Self {
-        let households = (0..initial_households)
-            .map(|n| {
-                Household::new(
-                    n as u32,
-                    match initial_genes {
-                        GeneSettings::Altruistic => Genes::altruistic(),
-                        GeneSettings::Defective => Genes::defective(),
-                        GeneSettings::Split => {
-                            if n % 2 == 0 {
-                                Genes::altruistic()
-                            } else {
-                                Genes::defective()
-                            }
-                        }
-                    },
-                )
-            })
+    // the households are given the ids starting from first_id
+    pub fn new(id: u32, position: Index, initial_genes: Vec<Genes>, first_id: u32) -> Self {
+        let households = initial_genes
+            .into_iter()
+            .enumerate()
+            .map(|(n, genes)| Household::new(first_id + n as u32, genes))
             .collect();
 
         Settlement {
@@ -42,41 +34,169 @@ impl Settlement {
         }
     }
 
-    pub fn query_donations(&mut self, i: usize, required: f64, rng: &mut ThreadRng) -> bool {
+    // asks the others for the resources household i requires, returning who would donate
+    //   and who could have but refused, without any resources changing hands
+    pub fn query_donations(
+        &self,
+        i: usize,
+        required: f64,
+        sharing: Sharing,
+        scheduling: Scheduling,
+        rng: &mut ThreadRng,
+    ) -> Response {
         let status = self.households[i].status();
 
-        // check with superiors first
-        for (j, other_household) in self.households.iter_mut().enumerate() {
-            if i != j && other_household.is_auth(status) {
-                if other_household.query_donation(required, QueryType::Subordinate, rng.gen()) {
-                    return true;
+        // the order in which the others are asked within each group
+        let mut others: Vec<usize> = (0..self.population()).filter(|&j| j != i).collect();
+        scheduling.arrange(
+            &mut others,
+            |&j| self.households[j].resources,
+            |&j| self.households[j].status(),
+            rng,
+        );
+
+        // superiors are asked first, then peers and subordinates last,
+        //   who are asked as superiors and so always donate if they can
+        let order: [(fn(&Household, f64) -> bool, QueryType); 3] = [
+            (Household::is_auth, QueryType::Subordinate),
+            (Household::is_peer, QueryType::Peer),
+            (Household::is_sub, QueryType::Superior),
+        ];
+
+        let mut response = Response {
+            donations: Vec::new(),
+            refusals: Vec::new(),
+        };
+        let mut received = 0.0;
+
+        for (relation, query_type) in order {
+            for &j in &others {
+                if received >= required {
+                    return response;
+                }
+
+                let other_household = &self.households[j];
+                if !relation(other_household, status) {
+                    continue;
+                }
+
+                // households that can't afford to donate haven't refused
+                let amount = other_household.affordable(required - received, sharing);
+                if amount <= 0.0 {
+                    continue;
                 }
-            }
-        }
 
-        // check with peers second
-        for (j, other_household) in self.households.iter_mut().enumerate() {
-            if i != j && other_household.is_peer(status) {
-                if other_household.query_donation(required, QueryType::Peer, rng.gen()) {
-                    return true;
+                if other_household.willing(&self.households[i], query_type, rng.gen()) {
+                    received += amount;
+                    response.donations.push((j, amount));
+                } else {
+                    response.refusals.push(j);
                 }
             }
         }
 
-        // check with subordinates last
-        for (j, other_household) in self.households.iter_mut().enumerate() {
-            if i != j && other_household.is_sub(status) {
-                if other_household.query_donation(required, QueryType::Superior, 0.0) {
-                    return true;
+        response
+    }
+
+    // gives household i the donations of the response, where it remembers who helped
+    //   and who refused it and their images change, returning how much it received
+    pub fn transfer(
+        &mut self,
+        i: usize,
+        response: &Response,
+        memory: usize,
+        image_step: f64,
+    ) -> f64 {
+        let mut received = 0.0;
+
+        for &(j, amount) in &response.donations {
+            self.households[j].donate(amount);
+            received += amount;
+
+            let donor = self.households[j].id;
+            self.households[i].owe(donor, amount);
+            self.households[i].remember(donor, true, memory);
+            self.households[j].update_image(true, image_step);
+        }
+
+        for &j in &response.refusals {
+            let refuser = self.households[j].id;
+            self.households[i].remember(refuser, false, memory);
+            self.households[j].update_image(false, image_step);
+        }
+
+        self.households[i].provide(received);
+        received
+    }
+
+    // the donations of requests made together that donors can make, where with all-or-nothing
+    //   sharing promises are kept in order while the donor can keep them in full and the rest
+    //   become refusals, and with pooled sharing donors that promised more than they have
+    //   give proportionally less
+    pub fn honour(
+        &self,
+        requests: Vec<(usize, Response)>,
+        sharing: Sharing,
+    ) -> Vec<(usize, Response)> {
+        match sharing {
+            Sharing::AllOrNothing => {
+                let mut remaining: Vec<f64> = self.households.iter().map(|h| h.resources).collect();
+
+                requests
+                    .into_iter()
+                    .map(|(i, mut response)| {
+                        let (kept, broken): (Vec<_>, Vec<_>) =
+                            response.donations.into_iter().partition(|&(j, amount)| {
+                                if amount <= remaining[j] {
+                                    remaining[j] -= amount;
+                                    true
+                                } else {
+                                    false
+                                }
+                            });
+
+                        response.donations = kept;
+                        response.refusals.extend(broken.into_iter().map(|(j, _)| j));
+                        (i, response)
+                    })
+                    .collect()
+            }
+            Sharing::Pooled => {
+                let mut promised = vec![0.0; self.population()];
+                for (_, response) in &requests {
+                    for &(j, amount) in &response.donations {
+                        promised[j] += amount;
+                    }
                 }
+
+                let scale: Vec<f64> = promised
+                    .iter()
+                    .zip(&self.households)
+                    .map(|(&p, h)| {
+                        if p > h.resources {
+                            h.resources / p
+                        } else {
+                            1.0
+                        }
+                    })
+                    .collect();
+
+                requests
+                    .into_iter()
+                    .map(|(i, mut response)| {
+                        for (j, amount) in &mut response.donations {
+                            *amount *= scale[*j];
+                        }
+
+                        (i, response)
+                    })
+                    .collect()
             }
         }
-
-        false
     }
 
-    pub fn influence(&self, other: &Self) -> f64 {
-        other.status().powf(crate::beta) - crate::m * self.position.dist(other.position)
+    pub fn influence(&self, other: &Self, topology: &Topology) -> f64 {
+        other.status().powf(crate::beta) - crate::m * self.position.dist(other.position, topology)
     }
 
     pub fn status(&self) -> f64 {
@@ -94,33 +214,149 @@ impl Settlement {
         self.households.swap_remove(i)
     }
 
-    // the household with id pairs with another household with genes
-    pub fn add(&mut self, id: u32, genes: Genes) {
-        let new_id = self.max_id() + 1;
+    // the household with id pairs with a mate with genes,
+    //   and has a child with new_id that may live in another settlement
+    pub fn birth(
+        &mut self,
+        id: u32,
+        genes: Genes,
+        mate: Option<u32>,
+        new_id: u32,
+        heredity: &Heredity,
+    ) -> Household {
         let pos = self.pos(id);
 
-        let new_household = self.households[pos].birth_new(genes, new_id);
-        self.households.push(new_household);
+        self.households[pos].birth_new(genes, mate, new_id, heredity)
     }
 
-    fn max_id(&self) -> u32 {
-        self.households.iter().map(|h| h.id).max().unwrap_or(0)
+    pub fn add(&mut self, household: Household) {
+        self.households.push(household);
+    }
+
+    // every household repays the fraction of its resources to the households it owes,
+    //   oldest debts first, returning how much was repaid
+    pub fn repay(&mut self, fraction: f64) -> f64 {
+        let mut repaid = 0.0;
+
+        for i in 0..self.population() {
+            let mut budget = self.households[i].resources * fraction;
+            let mut debts = std::mem::take(&mut self.households[i].debts);
+
+            for (creditor, debt) in &mut debts {
+                // debts to households that have died can't be repaid
+                let Some(j) = self.households.iter().position(|h| h.id == *creditor) else {
+                    *debt = 0.0;
+                    continue;
+                };
+
+                let payment = f64::min(budget, *debt);
+                if payment <= 0.0 {
+                    continue;
+                }
+
+                self.households[i].resources -= payment;
+                self.households[j].resources += payment;
+                self.households[j].load = f64::max(self.households[j].load - payment, 0.0);
+
+                *debt -= payment;
+                budget -= payment;
+                repaid += payment;
+            }
+
+            debts.retain(|&(_, debt)| debt > 0.0);
+            self.households[i].debts = debts;
+        }
+
+        repaid
+    }
+
+    // passes on what a household that died owned, returning its patch if nobody inherits it
+    pub fn inherit(
+        &mut self,
+        household: Household,
+        resources: ResourceInheritance,
+        land: LandInheritance,
+    ) -> Option<Index> {
+        let children: Vec<_> = (0..self.households.len())
+            .filter(|&i| self.households[i].parent == Some(household.id))
+            .collect();
+
+        let youngest = |children: &[usize]| {
+            children
+                .iter()
+                .copied()
+                .min_by_key(|&i| self.households[i].age)
+        };
+        let eldest = |children: &[usize]| {
+            children
+                .iter()
+                .copied()
+                .max_by_key(|&i| self.households[i].age)
+        };
+
+        let heirs = match resources {
+            ResourceInheritance::None => Vec::new(),
+            ResourceInheritance::Youngest => youngest(&children).into_iter().collect(),
+            ResourceInheritance::Eldest => eldest(&children).into_iter().collect(),
+            ResourceInheritance::Equal => children.clone(),
+            ResourceInheritance::Settlement => (0..self.households.len()).collect(),
+        };
+
+        // only children without a patch of their own can inherit one
+        let landless: Vec<_> = children
+            .iter()
+            .copied()
+            .filter(|&i| self.households[i].resource_patch.is_none())
+            .collect();
+
+        let heir = match land {
+            LandInheritance::Release => None,
+            LandInheritance::Youngest => youngest(&landless),
+            LandInheritance::Eldest => eldest(&landless),
+        };
+
+        for &i in &heirs {
+            self.households[i].resources += household.resources / heirs.len() as f64;
+        }
+
+        match heir {
+            Some(i) => {
+                self.households[i].resource_patch = household.resource_patch;
+                None
+            }
+            None => household.resource_patch,
+        }
     }
 
     fn pos(&self, id: u32) -> usize {
         self.households.iter().position(|h| h.id == id).unwrap()
     }
 
-    pub fn find_genes(&self, mut status: f64) -> Genes {
-        for household in &self.households {
-            if status <= household.load {
-                return household.genes;
+    // the index of the household at a point on a roulette wheel,
+    //   where each household takes up as much of the wheel as its load,
+    //   apart from the excluded household, which takes up none of it
+    pub fn find_partner(&self, mut status: f64, excluded: Option<usize>) -> Option<usize> {
+        let mut last = None;
+
+        for (i, household) in self.households.iter().enumerate() {
+            if Some(i) == excluded {
+                continue;
+            }
+
+            if household.load > 0.0 {
+                last = Some(i);
+            }
+
+            if status < household.load {
+                return Some(i);
             }
 
             status -= household.load;
         }
 
-        panic!("status was > the sum of all statuses")
+        // rounding can leave a little of the wheel over,
+        //   which belongs to the last household with any load
+        last
     }
 
     pub fn population(&self) -> usize {
@@ -142,16 +378,8 @@ impl Settlement {
             / self.population() as f64
     }
 
-    pub fn cooperation(&self) -> (f64, f64) {
-        let coop_sum = self.households.iter().fold((0.0, 0.0), |a, h| {
-            (
-                a.0 + h.genes.peer_transfer,
-                a.1 + h.genes.subordinate_transfer,
-            )
-        });
-
-        let pop = self.population() as f64;
-        (coop_sum.0 / pop, coop_sum.1 / pop)
+    pub fn average_trait(&self, t: Trait) -> f64 {
+        self.households.iter().map(|h| h.genes.get(t)).sum::<f64>() / self.population() as f64
     }
 
     pub fn statuses(&self) -> Vec<f64> {
@@ -176,3 +404,74 @@ impl Settlement {
             .fold(0.0 / 0.0, f64::max)
     }
 }
+
+/// Who inherits the resources of a household when it dies
+#[derive(Clone, Copy, ValueEnum)]
+pub enum ResourceInheritance {
+    /// The resources are lost
+    None,
+    /// The youngest child inherits everything
+    Youngest,
+    /// The eldest child inherits everything
+    Eldest,
+    /// The children inherit equal shares
+    Equal,
+    /// Every household in the settlement inherits an equal share
+    Settlement,
+}
+
+/// Who inherits the resource patch of a household when it dies
+#[derive(Clone, Copy, ValueEnum)]
+pub enum LandInheritance {
+    /// The patch becomes unclaimed
+    Release,
+    /// The youngest child without a patch inherits it
+    Youngest,
+    /// The eldest child without a patch inherits it
+    Eldest,
+}
+
+/// Where a newborn household lives
+#[derive(Clone, Copy, ValueEnum)]
+pub enum Residence {
+    /// In the settlement of its parent
+    Parent,
+    /// In the settlement of its parent's partner
+    Partner,
+    /// In a random settlement
+    Random,
+    /// In the settlement with the most influence over its parent's settlement
+    Best,
+}
+
+/// The order in which households make requests or are asked to donate
+#[derive(Clone, Copy, ValueEnum)]
+pub enum Scheduling {
+    /// The order of the households in their settlement
+    Sequential,
+    /// A new random order each time
+    Random,
+    /// Those that need the most ask first, and those with the most resources are asked first
+    Need,
+    /// Those with the highest status first
+    Status,
+}
+
+impl Scheduling {
+    // puts the items in the order of the schedule, given how much each one needs
+    //   (or has to give) and its status, where ties keep their order
+    pub fn arrange<T>(
+        &self,
+        items: &mut [T],
+        need: impl Fn(&T) -> f64,
+        status: impl Fn(&T) -> f64,
+        rng: &mut ThreadRng,
+    ) {
+        match self {
+            Scheduling::Sequential => {}
+            Scheduling::Random => items.shuffle(rng),
+            Scheduling::Need => items.sort_by(|a, b| need(b).total_cmp(&need(a))),
+            Scheduling::Status => items.sort_by(|a, b| status(b).total_cmp(&status(a))),
+        }
+    }
+}
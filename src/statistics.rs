@@ -0,0 +1,87 @@
+// summaries of the distribution of a trait across households
+
+pub fn mean(values: &[f64]) -> f64 {
+    values.iter().sum::<f64>() / values.len() as f64
+}
+
+// the population variance
+pub fn variance(values: &[f64]) -> f64 {
+    let mean = mean(values);
+    values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / values.len() as f64
+}
+
+// the number of values between 0 and 1 in each of the equally wide bins,
+//   where the last bin includes 1
+pub fn histogram(values: &[f64], bins: usize) -> Vec<usize> {
+    let mut histogram = vec![0; bins];
+
+    for value in values {
+        let bin = (value.clamp(0.0, 1.0) * bins as f64) as usize;
+        histogram[usize::min(bin, bins - 1)] += 1;
+    }
+
+    histogram
+}
+
+// Sarle's bimodality coefficient, which is above 5/9 for bimodal or uniform distributions
+//   and 1 for two equally sized groups of identical values
+pub fn bimodality(values: &[f64]) -> f64 {
+    let n = values.len() as f64;
+    let mean = mean(values);
+    let variance = variance(values);
+
+    let moment = |k| values.iter().map(|v| (v - mean).powi(k)).sum::<f64>() / n;
+    let skewness = moment(3) / variance.powf(1.5);
+    let kurtosis = moment(4) / variance.powi(2) - 3.0;
+
+    (skewness.powi(2) + 1.0) / (kurtosis + 3.0 * (n - 1.0).powi(2) / ((n - 2.0) * (n - 3.0)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    #[test]
+    fn the_bounds_fall_in_the_first_and_last_bins() {
+        assert_eq!(
+            histogram(&[0.0, 0.05, 0.5, 0.95, 1.0], 10),
+            [2, 0, 0, 0, 0, 1, 0, 0, 0, 2]
+        );
+        assert_eq!(histogram(&[-0.5, 1.5], 2), [1, 1]);
+    }
+
+    #[test]
+    fn two_groups_are_bimodal() {
+        let split: Vec<_> = (0..1000).map(|i| (i % 2) as f64).collect();
+        assert!(bimodality(&split) > 0.99);
+
+        // a peak with a few values either side is far from bimodal
+        let peak: Vec<_> = (0..1000)
+            .map(|i| match i % 6 {
+                0 => 0.4,
+                1 => 0.6,
+                _ => 0.5,
+            })
+            .collect();
+        assert!(bimodality(&peak) < 5.0 / 9.0);
+    }
+
+    proptest! {
+        #[test]
+        fn every_value_is_counted_once(
+            values in prop::collection::vec(0.0..=1.0f64, 0..100),
+            bins in 1..20usize,
+        ) {
+            let histogram = histogram(&values, bins);
+
+            prop_assert_eq!(histogram.len(), bins);
+            prop_assert_eq!(histogram.iter().sum::<usize>(), values.len());
+        }
+
+        #[test]
+        fn the_variance_is_not_negative(values in prop::collection::vec(0.0..=1.0f64, 1..100)) {
+            prop_assert!(variance(&values) >= 0.0);
+        }
+    }
+}
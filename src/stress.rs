@@ -0,0 +1,445 @@
+use crate::{config::invalid, Settings};
+use clap::ValueEnum;
+use csv::Reader;
+use rand::{rngs::ThreadRng, thread_rng};
+use rand_distr::{Distribution, Normal, Poisson};
+use std::{
+    f64::consts::PI,
+    fmt::Display,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::Arc,
+};
+
+// the environment is a value from 0 (harshest) to 1 (most plentiful)
+//   that is mapped into the bounds of the resources a patch produces
+#[derive(Clone)]
+pub enum Scenario {
+    // a sinusoid with the frequency of the simulation
+    Sinusoid,
+    // a time series of environmental values read from a file
+    Series(Arc<Series>),
+    Constant {
+        level: f64,
+    },
+    // the environment changes once at the given iteration
+    Step {
+        before: f64,
+        after: f64,
+        at: u32,
+    },
+    // the environment changes linearly over the whole simulation
+    Trend {
+        start: f64,
+        end: f64,
+    },
+    // autocorrelated noise that reverts to the mean
+    Autoregressive {
+        mean: f64,
+        phi: f64,
+        sigma: f64,
+    },
+    RandomWalk {
+        start: f64,
+        sigma: f64,
+    },
+    // droughts arrive as a Poisson process with the given rate per iteration,
+    //   and reduce the environment by a fraction for a number of iterations
+    Droughts {
+        level: f64,
+        rate: f64,
+        severity: f64,
+        duration: u32,
+    },
+}
+
+impl FromStr for Scenario {
+    type Err = String;
+
+    // scenarios are written as "name:parameter,parameter,..."
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, parameters) = s.split_once(':').unwrap_or((s, ""));
+
+        let p = parameters
+            .split(',')
+            .filter(|p| !p.trim().is_empty())
+            .map(|p| p.trim().parse::<f64>().map_err(|e| e.to_string()))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let scenario = match (name, &p[..]) {
+            ("sinusoid", []) => Scenario::Sinusoid,
+            ("constant", &[level]) => Scenario::Constant { level },
+            ("step", &[before, after, at]) => Scenario::Step {
+                before,
+                after,
+                at: at as u32,
+            },
+            ("trend", &[start, end]) => Scenario::Trend { start, end },
+            ("ar1", &[mean, phi, sigma]) => Scenario::Autoregressive { mean, phi, sigma },
+            ("random-walk", &[start, sigma]) => Scenario::RandomWalk { start, sigma },
+            ("drought", &[level, rate, severity, duration]) => Scenario::Droughts {
+                level,
+                rate,
+                severity,
+                duration: duration as u32,
+            },
+            _ => {
+                return Err(String::from(
+                    "expected one of sinusoid, constant:LEVEL, step:BEFORE,AFTER,AT, \
+                     trend:START,END, ar1:MEAN,PHI,SIGMA, random-walk:START,SIGMA \
+                     or drought:LEVEL,RATE,SEVERITY,DURATION",
+                ))
+            }
+        };
+
+        // the distributions can't be created with parameters that aren't finite
+        if p.iter().any(|p| !p.is_finite()) {
+            return Err(String::from("parameters must be finite"));
+        }
+
+        // or with negative ones, except for the coefficient of ar1,
+        //   which makes the environment oscillate around its mean
+        let negative = match scenario {
+            Scenario::Autoregressive { mean, sigma, .. } => mean < 0.0 || sigma < 0.0,
+            _ => p.iter().any(|&p| p < 0.0),
+        };
+
+        if negative {
+            return Err(String::from("parameters must not be negative"));
+        }
+
+        Ok(scenario)
+    }
+}
+
+impl Display for Scenario {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Scenario::Sinusoid => write!(f, "sinusoid"),
+            Scenario::Series(series) => write!(f, "series:{}", series.path.display()),
+            Scenario::Constant { level } => write!(f, "constant:{level}"),
+            Scenario::Step { before, after, at } => write!(f, "step:{before},{after},{at}"),
+            Scenario::Trend { start, end } => write!(f, "trend:{start},{end}"),
+            Scenario::Autoregressive { mean, phi, sigma } => write!(f, "ar1:{mean},{phi},{sigma}"),
+            Scenario::RandomWalk { start, sigma } => write!(f, "random-walk:{start},{sigma}"),
+            Scenario::Droughts {
+                level,
+                rate,
+                severity,
+                duration,
+            } => write!(f, "drought:{level},{rate},{severity},{duration}"),
+        }
+    }
+}
+
+pub struct Series {
+    path: PathBuf,
+    years: Vec<f64>,
+    values: Vec<f64>,
+    interpolation: Interpolation,
+    years_per_iteration: f64,
+}
+
+impl Series {
+    /// Reads a CSV file of years and environmental values, with a header row
+    pub fn from_csv(
+        path: impl AsRef<Path>,
+        interpolation: Interpolation,
+        rescaling: Rescaling,
+        years_per_iteration: f64,
+    ) -> Result<Self, csv::Error> {
+        let mut reader = Reader::from_path(&path)?;
+
+        let mut records = Vec::new();
+        for record in reader.deserialize() {
+            let (year, value): (f64, f64) = record?;
+
+            // these couldn't be sorted or interpolated
+            if !year.is_finite() || !value.is_finite() {
+                return Err(invalid(format!(
+                    "the year {year} and value {value} must be finite"
+                )));
+            }
+
+            records.push((year, value));
+        }
+
+        // otherwise every iteration would silently have the harshest environment
+        if records.is_empty() {
+            return Err(invalid(format!("{} has no years", path.as_ref().display())));
+        }
+
+        records.sort_by(|a, b| a.0.total_cmp(&b.0));
+        let (years, values) = records.into_iter().unzip();
+
+        Ok(Self::new(
+            path.as_ref().to_path_buf(),
+            years,
+            rescaling.rescale(values),
+            interpolation,
+            years_per_iteration,
+        ))
+    }
+
+    fn new(
+        path: PathBuf,
+        years: Vec<f64>,
+        values: Vec<f64>,
+        interpolation: Interpolation,
+        years_per_iteration: f64,
+    ) -> Self {
+        Series {
+            path,
+            years,
+            values,
+            interpolation,
+            years_per_iteration,
+        }
+    }
+
+    // the first year of the series is the first iteration,
+    //   and values beyond either end of the series are held constant
+    fn value(&self, t: u32) -> f64 {
+        if self.values.is_empty() {
+            return 0.0;
+        }
+
+        let year = self.years[0] + t as f64 * self.years_per_iteration;
+
+        // the index of the first recorded year after this one
+        let next = self.years.partition_point(|&y| y <= year);
+
+        if next == 0 {
+            return self.values[0];
+        }
+        if next == self.years.len() {
+            return self.values[next - 1];
+        }
+
+        let (y0, y1) = (self.years[next - 1], self.years[next]);
+        let (v0, v1) = (self.values[next - 1], self.values[next]);
+
+        match self.interpolation {
+            Interpolation::Step => v0,
+            Interpolation::Nearest => {
+                if year - y0 < y1 - year {
+                    v0
+                } else {
+                    v1
+                }
+            }
+            Interpolation::Linear => v0 + (year - y0) / (y1 - y0) * (v1 - v0),
+        }
+    }
+}
+
+/// How values between the recorded years of a series are estimated
+#[derive(Clone, Copy, ValueEnum)]
+pub enum Interpolation {
+    /// The value of the previous recorded year
+    Step,
+    /// The value of the closest recorded year
+    Nearest,
+    /// A straight line between the surrounding recorded years
+    Linear,
+}
+
+/// How the values of a series are mapped into the environment
+#[derive(Clone, Copy, ValueEnum)]
+pub enum Rescaling {
+    /// The values are already between 0 and 1, so they are only clamped
+    None,
+    /// The smallest value is mapped to 0 and the largest to 1
+    MinMax,
+    /// The smallest value is mapped to 1 and the largest to 0
+    Inverted,
+}
+
+impl Rescaling {
+    fn rescale(&self, values: Vec<f64>) -> Vec<f64> {
+        let min = values.iter().copied().fold(f64::INFINITY, f64::min);
+        let max = values.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+
+        // a constant series has no range to rescale
+        let range = if max > min { max - min } else { 1.0 };
+
+        values
+            .into_iter()
+            .map(|v| match self {
+                Rescaling::None => v.clamp(0.0, 1.0),
+                Rescaling::MinMax => (v - min) / range,
+                Rescaling::Inverted => 1.0 - (v - min) / range,
+            })
+            .collect()
+    }
+}
+
+// the resources produced by a patch are uniformly distributed between a minimum and maximum,
+//   each of which moves between a lower and upper value as the environment changes
+#[derive(Clone, Copy)]
+pub struct ResourceBounds {
+    pub min: (f64, f64),
+    pub max: (f64, f64),
+}
+
+impl ResourceBounds {
+    fn at(&self, environment: f64) -> (f64, f64) {
+        (
+            lerp(self.min.0, self.min.1, environment),
+            lerp(self.max.0, self.max.1, environment),
+        )
+    }
+}
+
+impl Default for ResourceBounds {
+    fn default() -> Self {
+        ResourceBounds {
+            min: (0.0, 0.6),
+            max: (0.4, 1.0),
+        }
+    }
+}
+
+impl FromStr for ResourceBounds {
+    type Err = String;
+
+    // the bounds are written as "min_low,min_high,max_low,max_high"
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let values = s
+            .split(',')
+            .map(|v| v.trim().parse::<f64>().map_err(|e| e.to_string()))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let [min_low, min_high, max_low, max_high] = values[..] else {
+            return Err(String::from("expected four comma-separated values"));
+        };
+
+        // resources can't be negative, and the bounds have to be numbers to be sampled
+        if values.iter().any(|v| !v.is_finite() || *v < 0.0) {
+            return Err(String::from("values must be finite and not negative"));
+        }
+
+        // otherwise the minimum could exceed the maximum
+        if min_low > max_low || min_high > max_high {
+            return Err(String::from("each minimum must not exceed its maximum"));
+        }
+
+        Ok(ResourceBounds {
+            min: (min_low, min_high),
+            max: (max_low, max_high),
+        })
+    }
+}
+
+impl Display for ResourceBounds {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{},{},{},{}",
+            self.min.0, self.min.1, self.max.0, self.max.1
+        )
+    }
+}
+
+pub struct ResourceGenerator {
+    scenario: Scenario,
+    bounds: ResourceBounds,
+    f: f64,
+    // the environment of the current iteration
+    environment: f64,
+    // the number of iterations left in the current drought
+    drought: u32,
+    rng: ThreadRng,
+}
+
+impl ResourceGenerator {
+    pub fn new(settings: &Settings) -> Self {
+        let mut generator = ResourceGenerator {
+            scenario: settings.scenario.clone(),
+            bounds: settings.bounds,
+            f: settings.f,
+            environment: 0.0,
+            drought: 0,
+            rng: thread_rng(),
+        };
+
+        generator.environment = generator.initial();
+        generator
+    }
+
+    fn s(x: f64, f: f64) -> f64 {
+        0.5 * (2.0 * PI * x * f).sin() + 0.5
+    }
+
+    fn initial(&mut self) -> f64 {
+        match self.scenario {
+            Scenario::Autoregressive { mean, .. } => mean.clamp(0.0, 1.0),
+            Scenario::RandomWalk { start, .. } => start.clamp(0.0, 1.0),
+            _ => self.next(0),
+        }
+    }
+
+    // the environment of iteration t, given the environment of the previous iteration
+    fn next(&mut self, t: u32) -> f64 {
+        let environment = match &self.scenario {
+            Scenario::Sinusoid => Self::s(t as f64 / crate::ITERATIONS as f64, self.f),
+            Scenario::Series(series) => series.value(t),
+            Scenario::Constant { level } => *level,
+            Scenario::Step { before, after, at } => {
+                if t < *at {
+                    *before
+                } else {
+                    *after
+                }
+            }
+            Scenario::Trend { start, end } => {
+                lerp(*start, *end, t as f64 / crate::ITERATIONS as f64)
+            }
+            Scenario::Autoregressive { mean, phi, sigma } => {
+                let noise = Normal::new(0.0, *sigma).unwrap().sample(&mut self.rng);
+                mean + phi * (self.environment - mean) + noise
+            }
+            Scenario::RandomWalk { sigma, .. } => {
+                self.environment + Normal::new(0.0, *sigma).unwrap().sample(&mut self.rng)
+            }
+            Scenario::Droughts {
+                level,
+                rate,
+                severity,
+                duration,
+            } => {
+                // overlapping droughts merge into one
+                if *rate > 0.0 && Poisson::new(*rate).unwrap().sample(&mut self.rng) >= 1.0 {
+                    self.drought = u32::max(self.drought, *duration);
+                }
+
+                if self.drought > 0 {
+                    self.drought -= 1;
+                    level * (1.0 - severity)
+                } else {
+                    *level
+                }
+            }
+        };
+
+        environment.clamp(0.0, 1.0)
+    }
+
+    /// Moves the environment on to iteration t
+    pub fn advance(&mut self, t: u32) {
+        self.environment = self.next(t);
+    }
+
+    pub fn environment(&self) -> f64 {
+        self.environment
+    }
+
+    /// The bounds of the resources produced by a patch in the current iteration
+    pub fn generate(&self) -> (f64, f64) {
+        self.bounds.at(self.environment)
+    }
+}
+
+fn lerp(r_min: f64, r_max: f64, x: f64) -> f64 {
+    r_min + x * (r_max - r_min)
+}
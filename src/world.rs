@@ -1,17 +1,56 @@
-use crate::{settlement::Settlement, Settings};
-use rand::{rngs::ThreadRng, thread_rng, Rng, RngCore};
-use std::{collections::VecDeque, f64::consts::PI};
+use crate::events::{Event, EventKind, Record};
+use crate::genealogy::Birth;
+use crate::genes::{Genes, Trait};
+use crate::household::{Cause, Household, Reproduction, Update};
+use crate::mating::choose_partner;
+use crate::settlement::{Residence, Settlement};
+use crate::{schedule::Schedule, stress::ResourceGenerator, Settings};
+use clap::ValueEnum;
+use rand::{rngs::ThreadRng, seq::index::sample, Rng, RngCore};
+use std::{
+    collections::{HashMap, VecDeque},
+    fmt::Display,
+};
+
+// the distance between the centres of adjacent rows of hexagons
+const HEX_ROW_HEIGHT: f64 = 0.866_025_403_784_438_6;
 
 pub struct World {
     settings: Settings,
     matrix: Vec<Vec<Cell>>,
     settlements: Vec<Settlement>,
+    generator: ResourceGenerator,
+    schedule: Schedule,
+    events: Vec<Event>,
+    event_log: Vec<Record>,
+    // the number of births and deaths of each cause in the last iteration
+    births: usize,
+    deaths: [usize; 3],
+    // the sum of the ages of the parents of those births
+    parent_ages: u32,
+    // the number of those births that moved to another settlement
+    dispersals: usize,
+    // the number of requests for resources, donations and resources donated in the last iteration
+    requests: usize,
+    donations: usize,
+    transferred: f64,
+    // the resources repaid to donors in the last iteration
+    repaid: f64,
+    // the id of the next household to be born
+    next_id: u32,
+    // the genes of the initial households, indexed by their ids
+    founders: Vec<Genes>,
+    // every birth, if the genealogy is being recorded
+    genealogy: Vec<Birth>,
+    // the household each household born since the ancestors were last marked descends from
+    //   through its parents, including the households alive then themselves
+    ancestors: HashMap<u32, u32>,
     iteration: u32,
     rng: ThreadRng,
 }
 
 impl World {
-    pub fn new(settings: Settings) -> Self {
+    pub fn new(mut settings: Settings) -> Self {
         let cells = crate::SIZE.pow(2);
 
         // create the matrix with all unclaimed cells
@@ -33,11 +72,16 @@ impl World {
                     if let Cell::Unclaimed = matrix[i][j] {
                         if new_index == 0 {
                             // create and place the settlement
+                            let genes = settings.initial_genes.generate(
+                                n as u32,
+                                crate::HOUSHOLDS,
+                                &mut rng,
+                            );
                             let settlement = Settlement::new(
                                 n as u32,
                                 Index(i, j),
-                                crate::HOUSHOLDS,
-                                settings.genes,
+                                genes,
+                                (n * crate::HOUSHOLDS) as u32,
                             );
                             settlements.push(settlement);
                             matrix[i][j] = Cell::Settled(n as u32);
@@ -51,16 +95,54 @@ impl World {
             }
         }
 
-        World {
+        let founders = settlements
+            .iter()
+            .flat_map(|s| s.households.iter().map(|h| h.genes))
+            .collect();
+
+        let mut schedule = settings.schedule.clone();
+        schedule.apply(0, &mut settings);
+
+        let mut world = World {
+            generator: ResourceGenerator::new(&settings),
+            schedule,
+            events: settings.events.clone(),
+            event_log: Vec::new(),
+            births: 0,
+            deaths: [0; 3],
+            parent_ages: 0,
+            dispersals: 0,
+            requests: 0,
+            donations: 0,
+            transferred: 0.0,
+            repaid: 0.0,
+            next_id: (crate::SETTLEMENTS * crate::HOUSHOLDS) as u32,
+            founders,
+            genealogy: Vec::new(),
+            ancestors: HashMap::new(),
             settings,
             matrix,
             settlements,
             iteration: 0,
             rng,
-        }
+        };
+
+        world.mark_ancestors();
+        world.apply_events();
+        world
     }
 
     pub fn iterate(&mut self) {
+        // births and deaths are only counted for the current iteration
+        self.births = 0;
+        self.deaths = [0; 3];
+        self.parent_ages = 0;
+        self.dispersals = 0;
+        self.requests = 0;
+        self.donations = 0;
+        self.transferred = 0.0;
+        self.repaid = 0.0;
+
         // agents without a resource patch try to claim one
         if self.count_population() < crate::SIZE.pow(2) {
             self.iterate_settlement();
@@ -69,13 +151,17 @@ impl World {
         // agents consume and request resources
         self.iterate_consumption();
 
+        // agents repay their debts and their load is forgotten
+        self.iterate_debt();
+
         // TODO:
         // agents reproduce based on their hunger
         // self.iterate_birth();
 
         // this is a check to ensure that free riders
         //  are not affecting the results
-        if self.count_population() < crate::SIZE.pow(2) {
+        let cap = self.settings.population_cap;
+        if cap == 0 || self.count_population() < cap {
             self.iterate_birth();
         }
 
@@ -88,7 +174,17 @@ impl World {
         // agents' resources degrade
         self.iterate_degradation();
 
+        // agents that survived grow older
+        self.iterate_ageing();
+
         self.iteration += 1;
+        self.generator.advance(self.iteration);
+
+        // parameters change at their scheduled iterations
+        self.schedule.apply(self.iteration, &mut self.settings);
+
+        // and interventions happen at theirs
+        self.apply_events();
     }
 
     fn iterate_settlement(&mut self) {
@@ -127,31 +223,64 @@ impl World {
     }
 
     fn iterate_consumption(&mut self) {
+        // every patch in the world is subject to the same environment
+        let (r_min, r_max) = self.generator.generate();
+
         for settlement in &mut self.settlements {
             let mut requests = Vec::new();
 
             for (i, household) in settlement.households.iter_mut().enumerate() {
                 // if a houshold has a resource patch, they gather resources from it
                 household.provide(if household.resource_patch.is_some() {
-                    Self::resources(self.iteration, self.settings.f)
+                    self.rng.gen_range(r_min..=r_max)
                 } else {
                     0.0
                 });
 
                 // the household returns how much they need
-                let required = household.required();
-                if required > 0.0 {
-                    requests.push((i, required));
+                if household.requesting() {
+                    requests.push((i, household.required()));
                 }
             }
 
-            // perform the requests
+            self.requests += requests.len();
+
+            let households = &settlement.households;
+            self.settings.request_order.arrange(
+                &mut requests,
+                |&(_, required)| required,
+                |&(i, _)| households[i].status(),
+                &mut self.rng,
+            );
+
+            let (memory, image_step) = (self.settings.memory, self.settings.image_step);
+
+            // perform the requests, whose donations are held back until every request
+            //   has been made if the update is synchronous
+            let mut pending = Vec::new();
             for (i, required) in requests {
-                if settlement.query_donations(i, required, &mut self.rng) {
-                    settlement.households[i].provide(required);
+                let response = settlement.query_donations(
+                    i,
+                    required,
+                    self.settings.sharing,
+                    self.settings.donor_order,
+                    &mut self.rng,
+                );
+
+                match self.settings.update {
+                    Update::Asynchronous => {
+                        self.donations += response.donations.len();
+                        self.transferred += settlement.transfer(i, &response, memory, image_step)
+                    }
+                    Update::Synchronous => pending.push((i, response)),
                 }
             }
 
+            for (i, response) in settlement.honour(pending, self.settings.sharing) {
+                self.donations += response.donations.len();
+                self.transferred += settlement.transfer(i, &response, memory, image_step);
+            }
+
             // having gathered and requested resources, agents consume them
             for household in &mut settlement.households {
                 household.consume();
@@ -159,69 +288,186 @@ impl World {
         }
     }
 
+    fn iterate_debt(&mut self) {
+        for settlement in &mut self.settlements {
+            if self.settings.repayment > 0.0 {
+                self.repaid += settlement.repay(self.settings.repayment);
+            }
+
+            if self.settings.load_decay > 0.0 {
+                for household in &mut settlement.households {
+                    household.decay_load(self.settings.load_decay);
+                }
+            }
+        }
+    }
+
     fn iterate_birth(&mut self) {
         let mut births: Vec<_> = (0..self.settlements.len()).map(|_| Vec::new()).collect();
 
         for (n, settlement) in self.settlements.iter().enumerate() {
-            let total_statuses = self
-                .settlements
-                .iter()
-                .filter(|s| settlement.influence(s) > 0.0)
-                .map(|s| s.status())
-                .sum::<f64>() as u32;
+            let birth_rate = self.settings.birth_rate * self.carrying_capacity(settlement);
 
-            for household in &settlement.households {
-                if household.birth(self.rng.gen()) {
-                    // we choose another partner from the possible options
-                    if total_statuses == 0 {
-                        births[n].push((household.id, household.genes));
-                    } else {
-                        let mut chosen = (self.rng.next_u32() % total_statuses) as f64;
-                        let mut genes = household.genes;
+            for (i, household) in settlement.households.iter().enumerate() {
+                if household.birth(self.rng.gen(), birth_rate, self.settings.maturity) {
+                    self.births += 1;
+                    self.parent_ages += household.age;
+
+                    // we choose another partner from the possible options,
+                    //   otherwise the household reproduces alone
+                    let partner =
+                        choose_partner(&self.settlements, n, i, &self.settings, &mut self.rng);
+
+                    births[n].push(match partner {
+                        Some((m, j)) => {
+                            let mate = &self.settlements[m].households[j];
+                            (household.id, mate.genes, Some(mate.id), m)
+                        }
+                        None => (household.id, household.genes, None, n),
+                    });
+                }
+            }
+        }
 
-                        for s in &self.settlements {
-                            if settlement.influence(s) <= 0.0 {
-                                continue;
-                            }
+        for (n, settlement_births) in births.iter().enumerate() {
+            for &(id, genes, mate, partner) in settlement_births {
+                let residence = self.residence(n, partner);
+                let child = self.settlements[n].birth(
+                    id,
+                    genes,
+                    mate,
+                    self.next_id,
+                    &self.settings.heredity,
+                );
+                self.next_id += 1;
+
+                if let Some(&ancestor) = self.ancestors.get(&id) {
+                    self.ancestors.insert(child.id, ancestor);
+                }
 
-                            if chosen <= settlement.status() {
-                                genes = settlement.find_genes(chosen);
-                                break;
-                            }
+                if residence != n {
+                    self.dispersals += 1;
+                }
 
-                            chosen -= settlement.status();
-                        }
+                if self.settings.genealogy {
+                    self.genealogy.push(Birth {
+                        iteration: self.iteration,
+                        child: child.id,
+                        parent: id,
+                        mate: child.mate,
+                        settlement: self.settlements[residence].id,
+                    });
+                }
+
+                self.settlements[residence].add(child);
+            }
+        }
+    }
+
+    // the index of the settlement a child of a household in settlement n will live in
+    fn residence(&mut self, n: usize, partner: usize) -> usize {
+        if self.rng.gen::<f64>() >= self.settings.dispersal {
+            return n;
+        }
+
+        match self.settings.residence {
+            Residence::Parent => n,
+            Residence::Partner => partner,
+            Residence::Random => self.rng.gen_range(0..self.settlements.len()),
+            Residence::Best => {
+                let influences: Vec<_> = self
+                    .settlements
+                    .iter()
+                    .map(|s| self.settlements[n].influence(s, &self.settings.topology))
+                    .collect();
+
+                (0..influences.len())
+                    .max_by(|&a, &b| influences[a].total_cmp(&influences[b]))
+                    .unwrap_or(n)
+            }
+        }
+    }
+
+    // how much the birth rate of a settlement is reduced by its surroundings,
+    //   from 0 (no births) to 1 (unaffected)
+    fn carrying_capacity(&self, settlement: &Settlement) -> f64 {
+        let density = || {
+            let population = settlement.population() as f64;
+            f64::max(1.0 - population / self.settings.settlement_capacity, 0.0)
+        };
 
-                        births[n].push((household.id, genes));
+        let land = || {
+            let (mut unclaimed, mut total) = (0, 0);
+
+            for i in 0..crate::SIZE {
+                for j in 0..crate::SIZE {
+                    let distance = settlement.position.dist(Index(i, j), &self.settings.topology);
+
+                    if distance <= self.settings.land_radius {
+                        total += 1;
+
+                        if let Cell::Unclaimed = self.matrix[i][j] {
+                            unclaimed += 1;
+                        }
                     }
                 }
             }
+
+            unclaimed as f64 / total as f64
+        };
+
+        match self.settings.reproduction {
+            Reproduction::Hunger => 1.0,
+            Reproduction::Density => density(),
+            Reproduction::Land => land(),
+            Reproduction::Combined => density() * land(),
         }
+    }
 
-        for (n, settlement_births) in births.iter().enumerate() {
-            for &(id, genes) in settlement_births {
-                self.settlements[n].add(id, genes);
+    fn iterate_death(&mut self) {
+        let mut deaths = Vec::new();
+        for settlement in &self.settlements {
+            let mut to_remove = Vec::new();
+
+            for household in &settlement.households {
+                let cause = household.death(
+                    self.rng.gen(),
+                    self.settings.death_rate,
+                    self.settings.mortality,
+                    self.settings.gompertz,
+                );
+
+                if let Some(cause) = cause {
+                    self.deaths[cause as usize] += 1;
+                    to_remove.push(household.id);
+                }
             }
+
+            deaths.push(to_remove);
         }
+
+        self.remove_households(deaths);
     }
 
-    fn iterate_death(&mut self) {
+    // each vector holds the ids of the households to remove from that settlement
+    fn remove_households(&mut self, to_remove: Vec<Vec<u32>>) {
         let mut settlements_to_remove = Vec::new();
 
-        for settlement in &mut self.settlements {
-            let to_remove: Vec<_> = settlement
+        for (settlement, to_remove) in self.settlements.iter_mut().zip(to_remove) {
+            // the households are removed first so that they can't inherit from each other
+            let removed: Vec<_> = settlement
                 .households
-                .iter()
-                .filter(|h| h.death(self.rng.gen()))
-                .map(|h| h.id)
+                .extract_if(|h| to_remove.contains(&h.id))
                 .collect();
 
-            let removed = settlement
-                .households
-                .extract_if(|h| to_remove.contains(&h.id));
-
             for household in removed {
-                if let Some(pos) = household.resource_patch {
+                let released = settlement.inherit(
+                    household,
+                    self.settings.resource_inheritance,
+                    self.settings.land_inheritance,
+                );
+
+                if let Some(pos) = released {
                     self.matrix[pos.0][pos.1] = Cell::Unclaimed;
                 }
             }
@@ -246,22 +492,140 @@ impl World {
         // TODO -
     }
 
-    pub fn iterate_degradation(&mut self) {
+    fn apply_events(&mut self) {
+        let events: Vec<_> = self
+            .events
+            .iter()
+            .filter(|e| e.iteration == self.iteration)
+            .cloned()
+            .collect();
+
+        for event in events {
+            let record = self.apply_event(&event);
+            self.event_log.push(record);
+        }
+    }
+
+    fn apply_event(&mut self, event: &Event) -> Record {
+        let mut households = 0;
+        let mut resources = 0.0;
+
+        let targeted = |s: &Settlement| event.settlement.map_or(true, |id| id == s.id);
+
+        match event.kind {
+            EventKind::Wipe => {
+                for settlement in self.settlements.iter_mut().filter(|s| targeted(s)) {
+                    for household in &mut settlement.households {
+                        resources += household.resources * event.fraction;
+                        household.resources *= 1.0 - event.fraction;
+                        households += 1;
+                    }
+                }
+            }
+            EventKind::ResetLoad => {
+                for settlement in self.settlements.iter_mut().filter(|s| targeted(s)) {
+                    for household in &mut settlement.households {
+                        // the debts behind the load are forgiven with it
+                        household.load = 0.0;
+                        household.debts.clear();
+                        households += 1;
+                    }
+                }
+            }
+            EventKind::Cull | EventKind::Mutant => {
+                // a fraction of the households of each targeted settlement are chosen at random
+                let mut chosen = Vec::new();
+                for settlement in &self.settlements {
+                    let population = if targeted(settlement) {
+                        settlement.population()
+                    } else {
+                        0
+                    };
+
+                    let amount = (event.fraction * population as f64).round() as usize;
+                    chosen.push(sample(&mut self.rng, population, amount).into_vec());
+                }
+
+                households = chosen.iter().map(|c| c.len()).sum();
+
+                if let EventKind::Mutant = event.kind {
+                    for (settlement, chosen) in self.settlements.iter_mut().zip(&chosen) {
+                        for &i in chosen {
+                            event.mutate(&mut settlement.households[i].genes);
+                        }
+                    }
+                } else {
+                    let mut to_remove = Vec::new();
+                    for (settlement, chosen) in self.settlements.iter().zip(&chosen) {
+                        resources += chosen
+                            .iter()
+                            .map(|&i| settlement.households[i].resources)
+                            .sum::<f64>();
+                        to_remove.push(chosen.iter().map(|&i| settlement.households[i].id).collect());
+                    }
+
+                    self.remove_households(to_remove);
+                }
+            }
+        }
+
+        Record {
+            iteration: self.iteration,
+            kind: event.kind,
+            settlement: event.settlement,
+            households,
+            resources,
+        }
+    }
+
+    // gives count random households of the settlement with the id, or of a random settlement
+    //   if there is none, the genes, returning how many there were
+    pub fn introduce(&mut self, id: Option<u32>, count: usize, genes: Genes) -> usize {
+        let n = match id {
+            Some(id) => self.settlements.iter().position(|s| s.id == id),
+            None if self.settlements.is_empty() => None,
+            None => Some(self.rng.gen_range(0..self.settlements.len())),
+        };
+
+        let Some(n) = n else {
+            return 0;
+        };
+
+        let settlement = &mut self.settlements[n];
+        let count = usize::min(count, settlement.population());
+
+        for i in sample(&mut self.rng, settlement.population(), count) {
+            settlement.households[i].genes = genes;
+        }
+
+        count
+    }
+
+    pub fn event_log(&self) -> &[Record] {
+        &self.event_log
+    }
+
+    fn iterate_ageing(&mut self) {
         for settlement in self.settlements.iter_mut() {
             for household in settlement.households.iter_mut() {
-                household.resources *= 1.0 - self.settings.degradation;
+                household.age += 1;
             }
         }
     }
 
-    pub fn resources(t: u32, f: f64) -> f64 {
-        ResourceGenerator::generate(t, f)
+    pub fn iterate_degradation(&mut self) {
+        for settlement in self.settlements.iter_mut() {
+            for household in settlement.households.iter_mut() {
+                household.resources *= 1.0 - self.settings.degradation;
+            }
+        }
     }
 
     // this is a simple grid traversal algorithm
     pub fn find_unclaimed_patch(&self, pos: Index, id: u32) -> Option<Index> {
+        let topology = &self.settings.topology;
         let mut searched = vec![pos];
-        let mut to_search = VecDeque::from(pos.surroundings(crate::SIZE as isize));
+        let mut to_search = VecDeque::from(pos.surroundings(topology));
 
         while !to_search.is_empty() {
             let current_pos = to_search.pop_front().unwrap();
@@ -284,8 +648,7 @@ impl World {
             // otherwise we are trespassing
             if let Cell::Claimed(cid) = cell {
                 if *cid == id {
-                    let mut surroundings =
-                        VecDeque::from(current_pos.surroundings(crate::SIZE as isize));
+                    let mut surroundings = VecDeque::from(current_pos.surroundings(topology));
                     to_search.append(&mut surroundings);
                 }
             }
@@ -298,6 +661,113 @@ impl World {
         self.iteration
     }
 
+    pub fn settings(&self) -> &Settings {
+        &self.settings
+    }
+
+    pub fn environment(&self) -> f64 {
+        self.generator.environment()
+    }
+
+    pub fn count_births(&self) -> usize {
+        self.births
+    }
+
+    // the sum of the ages of the parents of the births in the last iteration
+    pub fn parent_ages(&self) -> u32 {
+        self.parent_ages
+    }
+
+    pub fn count_dispersals(&self) -> usize {
+        self.dispersals
+    }
+
+    pub fn count_requests(&self) -> usize {
+        self.requests
+    }
+
+    pub fn count_donations(&self) -> usize {
+        self.donations
+    }
+
+    // the resources donated in the last iteration
+    pub fn transferred(&self) -> f64 {
+        self.transferred
+    }
+
+    pub fn repaid(&self) -> f64 {
+        self.repaid
+    }
+
+    pub fn average_image(&self) -> f64 {
+        self.households().map(|(_, h)| h.image).sum::<f64>() / self.count_population() as f64
+    }
+
+    // the resources households owe for the donations they received
+    pub fn total_debt(&self) -> f64 {
+        self.households().map(|(_, h)| h.debt()).sum()
+    }
+
+    pub fn count_deaths(&self, cause: Cause) -> usize {
+        self.deaths[cause as usize]
+    }
+
+    pub fn genealogy(&self) -> &[Birth] {
+        &self.genealogy
+    }
+
+    // makes every current household its own ancestor and forgets the rest
+    pub fn mark_ancestors(&mut self) {
+        self.ancestors = self
+            .settlements
+            .iter()
+            .flat_map(|s| s.households.iter().map(|h| (h.id, h.id)))
+            .collect();
+    }
+
+    // the household alive when the ancestors were last marked that this one descends from
+    pub fn ancestor(&self, id: u32) -> Option<u32> {
+        self.ancestors.get(&id).copied()
+    }
+
+    // every household with the index of its settlement
+    pub fn households(&self) -> impl Iterator<Item = (usize, &Household)> {
+        self.settlements
+            .iter()
+            .enumerate()
+            .flat_map(|(n, s)| s.households.iter().map(move |h| (n, h)))
+    }
+
+    pub fn count_founders(&self) -> usize {
+        self.founders.len()
+    }
+
+    // the number of initial households with surviving descendants through their parents,
+    //   split by whether the founder was more altruistic or defective
+    pub fn count_lineages(&self) -> (usize, usize) {
+        let mut surviving = vec![false; self.founders.len()];
+        for settlement in &self.settlements {
+            for household in &settlement.households {
+                surviving[household.founder as usize] = true;
+            }
+        }
+
+        let (mut altruistic, mut defective) = (0, 0);
+        for (founder, genes) in self.founders.iter().enumerate() {
+            if !surviving[founder] {
+                continue;
+            }
+
+            if genes.cooperation() >= 0.5 {
+                altruistic += 1;
+            } else {
+                defective += 1;
+            }
+        }
+
+        (altruistic, defective)
+    }
+
     pub fn count_settlements(&self) -> usize {
         self.settlements.len()
     }
@@ -306,6 +776,20 @@ impl World {
         self.settlements.iter().map(|s| s.population()).sum()
     }
 
+    // the number of households whose genes are closer to the mutant's than the resident's,
+    //   so children of either parent count as whichever strategy they inherited
+    pub fn count_mutants(&self, resident: &Genes, mutant: &Genes) -> usize {
+        self.settlements
+            .iter()
+            .map(|s| {
+                s.households
+                    .iter()
+                    .filter(|h| h.genes.distance(mutant) < h.genes.distance(resident))
+                    .count()
+            })
+            .sum()
+    }
+
     pub fn count_patches(&self) -> usize {
         self.settlements.iter().map(|s| s.patches()).sum()
     }
@@ -318,14 +802,43 @@ impl World {
             / self.count_settlements() as f64
     }
 
-    pub fn cooperation(&self) -> (f64, f64) {
-        let transfer = self.settlements.iter().fold((0.0, 0.0), |a, s| {
-            let coop = s.cooperation();
-            (a.0 + coop.0, a.1 + coop.1)
-        });
+    pub fn average_trait(&self, t: Trait) -> f64 {
+        self.settlements
+            .iter()
+            .map(|s| s.average_trait(t))
+            .sum::<f64>()
+            / self.count_settlements() as f64
+    }
+
+    // the value of the trait of every household
+    pub fn traits(&self, t: Trait) -> Vec<f64> {
+        self.settlements
+            .iter()
+            .flat_map(|s| s.households.iter().map(move |h| h.genes.get(t)))
+            .collect()
+    }
+
+    pub fn average_age(&self) -> f64 {
+        self.ages().iter().sum::<u32>() as f64 / self.count_population() as f64
+    }
+
+    pub fn ages(&self) -> Vec<u32> {
+        self.settlements
+            .iter()
+            .flat_map(|s| s.households.iter().map(|h| h.age))
+            .collect()
+    }
+
+    // the number of households in each age group, where the last group includes all older households
+    pub fn age_pyramid(&self, width: u32, groups: u32) -> Vec<usize> {
+        let mut pyramid = vec![0; groups as usize];
 
-        let set = self.count_settlements() as f64;
-        (transfer.0 / set, transfer.1 / set)
+        for age in self.ages() {
+            let group = u32::min(age / width, groups - 1);
+            pyramid[group as usize] += 1;
+        }
+
+        pyramid
     }
 
     pub fn average_resources(&self) -> f64 {
@@ -394,51 +907,135 @@ enum Cell {
 }
 
 #[derive(Clone, Copy, PartialEq, Eq)]
-pub struct Index(usize, usize);
+pub struct Index(pub usize, pub usize);
 
 impl Index {
-    fn surroundings(&self, size: isize) -> Vec<Index> {
-        let dirs = vec![(0, 1), (1, 0), (0, -1), (-1, 0)];
+    fn surroundings(&self, topology: &Topology) -> Vec<Index> {
+        let size = crate::SIZE as isize;
 
-        dirs.into_iter()
+        topology
+            .directions(self.0)
+            .iter()
             .map(|d| ((self.0 as isize) + d.0, (self.1 as isize) + d.1))
-            .filter(|s| s.0 >= 0 && s.0 < size && s.1 >= 0 && s.1 < size)
+            .filter_map(|s| {
+                if topology.toroidal {
+                    // opposite edges are joined, so we wrap around
+                    Some((s.0.rem_euclid(size), s.1.rem_euclid(size)))
+                } else if s.0 >= 0 && s.0 < size && s.1 >= 0 && s.1 < size {
+                    Some(s)
+                } else {
+                    None
+                }
+            })
             .map(|s| Index(s.0 as usize, s.1 as usize))
             .collect()
     }
 
-    pub fn dist(&self, other: Self) -> f64 {
-        let s = self.as_isize();
-        let o = other.as_isize();
+    pub fn dist(&self, other: Self, topology: &Topology) -> f64 {
+        let s = topology.centre(*self);
+        let o = topology.centre(other);
+        let extent = topology.extent();
 
-        let x = (s.0 - o.0).pow(2) as f64;
-        let y = (s.1 - o.1).pow(2) as f64;
+        let mut x = (s.0 - o.0).abs();
+        let mut y = (s.1 - o.1).abs();
 
-        (x + y).sqrt()
-    }
+        // on a torus the shortest path may cross an edge
+        if topology.toroidal {
+            x = f64::min(x, extent.0 - x);
+            y = f64::min(y, extent.1 - y);
+        }
 
-    fn as_isize(&self) -> (isize, isize) {
-        (self.0 as isize, self.1 as isize)
+        (x.powi(2) + y.powi(2)).sqrt()
     }
 }
 
-pub struct ResourceGenerator;
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Neighbourhood {
+    /// The four orthogonally adjacent cells
+    VonNeumann,
+    /// The eight orthogonally and diagonally adjacent cells
+    Moore,
+    /// The six adjacent cells of a hexagonal grid
+    Hexagonal,
+}
+
+#[derive(Clone, Copy)]
+pub struct Topology {
+    pub neighbourhood: Neighbourhood,
+    pub toroidal: bool,
+}
 
-impl ResourceGenerator {
-    fn s(x: f64, f: f64) -> f64 {
-        0.5 * (2.0 * PI * x * f).sin() + 0.5
+impl Topology {
+    pub fn new(neighbourhood: Neighbourhood, toroidal: bool) -> Self {
+        Topology {
+            neighbourhood,
+            toroidal,
+        }
     }
 
-    fn lerp(r_min: f64, r_max: f64, x: f64, f: f64) -> f64 {
-        r_min + Self::s(x, f) * (r_max - r_min)
+    // the hexagonal grid uses "odd-r" offset coordinates, where odd rows
+    //   are shifted half a cell to the right, so the directions depend on the row
+    // this only wraps correctly on a torus because SIZE is even
+    fn directions(&self, row: usize) -> &'static [(isize, isize)] {
+        match self.neighbourhood {
+            Neighbourhood::VonNeumann => &[(0, 1), (1, 0), (0, -1), (-1, 0)],
+            Neighbourhood::Moore => &[
+                (0, 1),
+                (1, 1),
+                (1, 0),
+                (1, -1),
+                (0, -1),
+                (-1, -1),
+                (-1, 0),
+                (-1, 1),
+            ],
+            Neighbourhood::Hexagonal if row % 2 == 0 => {
+                &[(0, 1), (1, 0), (1, -1), (0, -1), (-1, -1), (-1, 0)]
+            }
+            Neighbourhood::Hexagonal => &[(0, 1), (1, 1), (1, 0), (0, -1), (-1, 0), (-1, 1)],
+        }
     }
 
-    pub fn generate(t: u32, f: f64) -> f64 {
-        let x = t as f64 / crate::ITERATIONS as f64;
+    // the position of the centre of a cell in the plane
+    fn centre(&self, index: Index) -> (f64, f64) {
+        match self.neighbourhood {
+            Neighbourhood::Hexagonal => (
+                index.0 as f64 * HEX_ROW_HEIGHT,
+                index.1 as f64 + 0.5 * (index.0 % 2) as f64,
+            ),
+            _ => (index.0 as f64, index.1 as f64),
+        }
+    }
 
-        let a = Self::lerp(0.0, 0.6, x, f);
-        let b = Self::lerp(0.4, 1.0, x, f);
+    // the width and height of the whole grid in the plane
+    fn extent(&self) -> (f64, f64) {
+        let size = crate::SIZE as f64;
 
-        thread_rng().gen_range(a..b)
+        match self.neighbourhood {
+            Neighbourhood::Hexagonal => (size * HEX_ROW_HEIGHT, size),
+            _ => (size, size),
+        }
+    }
+}
+
+impl Default for Topology {
+    fn default() -> Self {
+        Topology::new(Neighbourhood::VonNeumann, false)
+    }
+}
+
+impl Display for Topology {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let neighbourhood = match self.neighbourhood {
+            Neighbourhood::VonNeumann => "von-neumann",
+            Neighbourhood::Moore => "moore",
+            Neighbourhood::Hexagonal => "hexagonal",
+        };
+
+        if self.toroidal {
+            write!(f, "{neighbourhood} (toroidal)")
+        } else {
+            write!(f, "{neighbourhood}")
+        }
     }
 }